@@ -0,0 +1,176 @@
+use std::{collections::HashSet, fs, net::IpAddr, path::Path};
+
+use crate::protocol::{dns_packet::DnsPacket, dns_record::DnsRecord, name::Name, presentation::parse_record, query_class::QueryClass, query_type::QueryType, reverse::reverse_name};
+
+// Records Nebula serves itself instead of resolving them
+pub struct Authority {
+  records: Vec<DnsRecord>,
+}
+
+impl Authority {
+  pub fn new() -> Self {
+    Self { records: Vec::new() }
+  }
+
+  pub fn records(&self) -> &[DnsRecord] {
+    &self.records
+  }
+
+  pub fn add(&mut self, record: DnsRecord) {
+    if !self.records.contains(&record) {
+      self.records.push(record);
+    }
+  }
+
+  // Load every record of a zone file, one record per line
+  pub fn load_zone_file(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let contents = fs::read_to_string(path)?;
+
+    for (number, line) in contents.lines().enumerate() {
+      let record = parse_record(line)
+        .map_err(|e| format!("{}:{}: {}", path.display(), number + 1, e))?;
+
+      if let Some(record) = record {
+        self.add(record);
+      }
+    }
+
+    Ok(())
+  }
+
+  // Add a PTR record for every local A and AAAA record, unless its reverse name already has one
+  pub fn add_reverse_zone(&mut self) {
+    let existing: HashSet<Name> = self.records
+      .iter()
+      .filter(|rec| matches!(rec, DnsRecord::PTR { .. }))
+      .map(|rec| rec.domain().clone())
+      .collect();
+
+    let ptrs: Vec<DnsRecord> = self.records
+      .iter()
+      .filter_map(|rec| match rec {
+        DnsRecord::A { domain, addr, class, ttl } => Some((IpAddr::V4(*addr), domain, *class, *ttl)),
+        DnsRecord::AAAA { domain, addr, class, ttl } => Some((IpAddr::V6(*addr), domain, *class, *ttl)),
+        _ => None,
+      })
+      .map(|(addr, domain, class, ttl)| DnsRecord::PTR { domain: reverse_name(addr), host: domain.clone(), class, ttl })
+      .filter(|ptr| !existing.contains(ptr.domain()))
+      .collect();
+
+    for ptr in ptrs {
+      self.add(ptr);
+    }
+  }
+
+  // Answer a question from local data. Names we hold nothing for give `None` so the query can be
+  // resolved normally, names we do hold but without this type give an empty NOERROR answer.
+  pub fn query(&self, qname: &Name, qtype: QueryType, qclass: QueryClass) -> Option<DnsPacket> {
+    let owned: Vec<&DnsRecord> = self.records
+      .iter()
+      .filter(|rec| rec.class().matches(qclass))
+      .filter(|rec| rec.domain() == qname)
+      .collect();
+
+    if owned.is_empty() {
+      return None;
+    }
+
+    let mut packet = DnsPacket::new();
+    packet.header.authoritative_answer = true;
+
+    packet.answers = owned.iter()
+      .filter(|rec| rec.query_type() == qtype)
+      .map(|rec| (*rec).clone())
+      .collect();
+
+    if packet.answers.is_empty() {
+      packet.answers = owned.iter()
+        .filter(|rec| matches!(rec, DnsRecord::CNAME { .. }))
+        .map(|rec| (*rec).clone())
+        .collect();
+    }
+
+    // Save the client a round trip by including the addresses of SRV targets we hold
+    let targets: Vec<Name> = packet.answers
+      .iter()
+      .filter_map(|rec| match rec {
+        DnsRecord::SRV { target, .. } => Some(target.clone()),
+        _ => None,
+      })
+      .collect();
+
+    packet.resources = self.records
+      .iter()
+      .filter(|rec| matches!(rec, DnsRecord::A { .. } | DnsRecord::AAAA { .. }) && rec.class().matches(qclass))
+      .filter(|rec| targets.contains(rec.domain()))
+      .cloned()
+      .collect();
+
+    Some(packet)
+  }
+}
+
+impl Default for Authority {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{protocol::reverse::ipv6_reverse_name, test_util::{a_record, aaaa_record, name}};
+
+  fn ptr_record(domain: &Name, host: &str) -> DnsRecord {
+    DnsRecord::PTR { domain: domain.clone(), host: name(host), class: QueryClass::IN, ttl: 300 }
+  }
+
+  #[test]
+  fn srv_answer_carries_target_addresses() {
+    let mut authority = Authority::new();
+    authority.add(DnsRecord::SRV {
+      domain: name("_sip._udp.example.com"),
+      priority: 10,
+      weight: 5,
+      port: 5060,
+      target: name("sip.example.com"),
+      class: QueryClass::IN,
+      ttl: 300,
+    });
+    authority.add(a_record("sip.example.com", 1));
+    authority.add(aaaa_record("sip.example.com", 1));
+    authority.add(a_record("www.example.com", 2));
+
+    let answer = authority.query(&name("_sip._udp.example.com"), QueryType::SRV, QueryClass::IN).unwrap();
+    assert_eq!(answer.answers.len(), 1);
+    assert_eq!(answer.resources, vec![a_record("sip.example.com", 1), aaaa_record("sip.example.com", 1)]);
+  }
+
+  #[test]
+  fn reverse_zone_answers_for_every_address() {
+    let mut authority = Authority::new();
+    authority.add(a_record("www.example.com", 1));
+    authority.add(aaaa_record("www.example.com", 1));
+    authority.add_reverse_zone();
+
+    let answer = authority.query(&name("1.2.0.192.in-addr.arpa"), QueryType::PTR, QueryClass::IN).unwrap();
+    assert_eq!(answer.answers, vec![ptr_record(&name("1.2.0.192.in-addr.arpa"), "www.example.com")]);
+
+    let reverse = ipv6_reverse_name("2001:db8::1".parse().unwrap());
+    let answer = authority.query(&reverse, QueryType::PTR, QueryClass::IN).unwrap();
+    assert_eq!(answer.answers, vec![ptr_record(&reverse, "www.example.com")]);
+  }
+
+  #[test]
+  fn reverse_zone_keeps_existing_ptr_records() {
+    let mut authority = Authority::new();
+    authority.add(a_record("www.example.com", 1));
+    authority.add(a_record("mail.example.com", 1));
+    authority.add(ptr_record(&name("1.2.0.192.in-addr.arpa"), "host.example.com"));
+    authority.add_reverse_zone();
+
+    let answer = authority.query(&name("1.2.0.192.in-addr.arpa"), QueryType::PTR, QueryClass::IN).unwrap();
+    assert_eq!(answer.answers, vec![ptr_record(&name("1.2.0.192.in-addr.arpa"), "host.example.com")]);
+    assert_eq!(authority.records().len(), 3);
+  }
+}
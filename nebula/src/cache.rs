@@ -0,0 +1,444 @@
+use std::{collections::{BTreeMap, HashMap}, net::IpAddr, time::{Duration, Instant}};
+
+use crate::protocol::{dns_packet::{DnsPacket, MAX_CNAME_CHAIN}, dns_record::DnsRecord, name::Name, query_class::QueryClass, query_type::QueryType, res_code::ResultCode};
+
+// Number of entries kept when no size is configured
+pub const DEFAULT_CACHE_SIZE: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+  pub name: Name,
+  // `None` covers every type, for names that do not exist at all
+  pub qtype: Option<QueryType>,
+  pub class: QueryClass,
+}
+
+impl CacheKey {
+  pub fn new(name: &Name, qtype: QueryType, class: QueryClass) -> Self {
+    Self { name: name.clone(), qtype: Some(qtype), class }
+  }
+
+  pub fn name_only(name: &Name, class: QueryClass) -> Self {
+    Self { name: name.clone(), qtype: None, class }
+  }
+}
+
+// How far a cached RRset can be trusted, by the section it came from (RFC 2181 section 5.4.1).
+// Data is never replaced by data that is less trustworthy while it is still fresh, so glue from a
+// referral can not overwrite an answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Credibility {
+  Additional,
+  Authority,
+  Answer,
+}
+
+enum CacheData {
+  Records(Vec<DnsRecord>),
+  // The name does not exist (RFC 2308 section 2.1), kept with the SOA from the authority section
+  NxDomain(Vec<DnsRecord>),
+  // The name exists but has no records of this type (RFC 2308 section 2.2)
+  NoData(Vec<DnsRecord>),
+}
+
+struct CacheEntry {
+  data: CacheData,
+  credibility: Credibility,
+  expires: Instant,
+  last_used: u64,
+}
+
+// Answers learned while resolving, expired by TTL and evicted least recently used first
+pub struct Cache {
+  entries: HashMap<CacheKey, CacheEntry>,
+  // Keys ordered from least to most recently used
+  lru: BTreeMap<u64, CacheKey>,
+  tick: u64,
+  max_size: usize,
+}
+
+impl Cache {
+  pub fn new(max_size: usize) -> Self {
+    Self {
+      entries: HashMap::new(),
+      lru: BTreeMap::new(),
+      tick: 0,
+      max_size,
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  // Look up an RRset. The TTLs returned count down from the moment it was stored.
+  pub fn lookup(&mut self, qname: &Name, qtype: QueryType, class: QueryClass) -> Option<Vec<DnsRecord>> {
+    match self.get(&CacheKey::new(qname, qtype, class))? {
+      CacheData::Records(records) => Some(records),
+      _ => None,
+    }
+  }
+
+  // Build a response to a question from the cache, following any CNAMEs held on the way and
+  // answering negatively when the name or type is known not to exist. Only data that came as an
+  // answer is given out, glue and referral NS records are kept for finding servers alone.
+  pub fn lookup_answer(&mut self, qname: &Name, qtype: QueryType, class: QueryClass) -> Option<DnsPacket> {
+    let mut packet = DnsPacket::new();
+    let mut name = qname.clone();
+
+    for _ in 0..MAX_CNAME_CHAIN {
+      if let Some(CacheData::NxDomain(authorities)) = self.get_answer(&CacheKey::name_only(&name, class)) {
+        packet.header.rescode = ResultCode::NXDOMIAN;
+        packet.authorities = authorities;
+        return Some(packet);
+      }
+
+      match self.get_answer(&CacheKey::new(&name, qtype, class)) {
+        Some(CacheData::Records(records)) => {
+          packet.answers.extend(records);
+          return Some(packet);
+        }
+        Some(CacheData::NoData(authorities)) => {
+          packet.authorities = authorities;
+          return Some(packet);
+        }
+        _ => {}
+      }
+
+      if qtype == QueryType::CNAME {
+        return None;
+      }
+
+      let Some(CacheData::Records(cname)) = self.get_answer(&CacheKey::new(&name, QueryType::CNAME, class)) else {
+        return None;
+      };
+      name = match cname.first() {
+        Some(DnsRecord::CNAME { host, .. }) => host.clone(),
+        _ => return None,
+      };
+      packet.answers.extend(cname);
+    }
+
+    None
+  }
+
+  // Store every RRset found in `records`, each expiring after the lowest TTL in the set
+  pub fn store(&mut self, records: &[DnsRecord], credibility: Credibility) {
+    let mut rrsets: HashMap<CacheKey, Vec<DnsRecord>> = HashMap::new();
+
+    for rec in records {
+      if let DnsRecord::OPT { .. } = rec {
+        continue;
+      }
+
+      let key = CacheKey::new(rec.domain(), rec.query_type(), rec.class());
+      let rrset = rrsets.entry(key).or_default();
+      if !rrset.contains(rec) {
+        rrset.push(rec.clone());
+      }
+    }
+
+    for (key, rrset) in rrsets {
+      let ttl = rrset.iter().map(|rec| rec.ttl()).min().unwrap_or(0);
+      if ttl == 0 {
+        continue;
+      }
+
+      self.insert(key, CacheData::Records(rrset), credibility, Duration::from_secs(ttl as u64));
+    }
+  }
+
+  // Remember that `qname` does not exist, or has no `qtype` records, when `response` says so.
+  // When the answer holds a CNAME chain the negative answer is about the name at its end, as the
+  // aliases do exist (RFC 2308 section 2.1), and the CNAMEs are stored as ordinary records.
+  // Negative answers without an SOA in the authority section are not cached (RFC 2308 section 5).
+  pub fn store_negative(&mut self, qname: &Name, qtype: QueryType, class: QueryClass, response: &DnsPacket) {
+    let (target, chain) = match qtype {
+      QueryType::CNAME => (qname.clone(), Vec::new()),
+      _ => response.get_cname_chain(qname),
+    };
+    let chain: Vec<DnsRecord> = chain.into_iter().cloned().collect();
+    self.store(&chain, Credibility::Answer);
+
+    let nxdomain = response.header.rescode == ResultCode::NXDOMIAN;
+    let nodata = response.header.rescode == ResultCode::NOERROR
+      && !response.answers.iter().any(|rec| rec.query_type() == qtype && *rec.domain() == target);
+    if !nxdomain && !nodata {
+      return;
+    }
+
+    let soa: Vec<DnsRecord> = response.authorities
+      .iter()
+      .filter(|rec| matches!(rec, DnsRecord::SOA { .. }))
+      .cloned()
+      .collect();
+
+    // The negative TTL is the lower of the SOA's own TTL and its MINIMUM field (RFC 2308 section 5)
+    let Some(ttl) = soa.iter().filter_map(|rec| match rec {
+      DnsRecord::SOA { minimum, ttl, .. } => Some(*minimum.min(ttl)),
+      _ => None,
+    }).min() else {
+      return;
+    };
+    if ttl == 0 {
+      return;
+    }
+
+    let ttl = Duration::from_secs(ttl as u64);
+    if nxdomain {
+      self.insert(CacheKey::name_only(&target, class), CacheData::NxDomain(soa), Credibility::Answer, ttl);
+    } else {
+      self.insert(CacheKey::new(&target, qtype, class), CacheData::NoData(soa), Credibility::Answer, ttl);
+    }
+  }
+
+  // The closest enclosing zone we have cached name server addresses for, IPv4 and IPv6, so a
+  // lookup can start below the root. Only the IN class is ever resolved.
+  pub fn closest_name_server(&mut self, qname: &Name) -> Option<(Name, Vec<IpAddr>)> {
+    let mut next = Some(qname.clone());
+
+    while let Some(zone) = next {
+      next = zone.parent();
+
+      let Some(ns_records) = self.lookup(&zone, QueryType::NS, QueryClass::IN) else {
+        continue;
+      };
+
+      let mut addrs = Vec::new();
+      for rec in ns_records {
+        let DnsRecord::NS { host, .. } = rec else {
+          continue;
+        };
+
+        for qtype in [QueryType::A, QueryType::AAAA] {
+          for rec in self.lookup(&host, qtype, QueryClass::IN).into_iter().flatten() {
+            let addr = match rec {
+              DnsRecord::A { addr, .. } => IpAddr::V4(addr),
+              DnsRecord::AAAA { addr, .. } => IpAddr::V6(addr),
+              _ => continue,
+            };
+
+            if !addrs.contains(&addr) {
+              addrs.push(addr);
+            }
+          }
+        }
+      }
+
+      if !addrs.is_empty() {
+        return Some((zone, addrs));
+      }
+    }
+
+    None
+  }
+
+  // Fetch an unexpired entry, with every TTL set to the time it has left
+  fn get(&mut self, key: &CacheKey) -> Option<CacheData> {
+    let now = Instant::now();
+
+    let expires = self.entries.get(key)?.expires;
+    if expires <= now {
+      self.remove(key);
+      return None;
+    }
+
+    self.touch(key);
+
+    let remaining = (expires - now).as_secs() as u32;
+    let with_ttl = |records: &[DnsRecord]| {
+      let mut records = records.to_vec();
+      for rec in &mut records {
+        rec.set_ttl(remaining);
+      }
+      records
+    };
+
+    Some(match &self.entries.get(key)?.data {
+      CacheData::Records(records) => CacheData::Records(with_ttl(records)),
+      CacheData::NxDomain(soa) => CacheData::NxDomain(with_ttl(soa)),
+      CacheData::NoData(soa) => CacheData::NoData(with_ttl(soa)),
+    })
+  }
+
+  // Like `get`, for entries that came from the answer section
+  fn get_answer(&mut self, key: &CacheKey) -> Option<CacheData> {
+    if self.entries.get(key)?.credibility < Credibility::Answer {
+      return None;
+    }
+
+    self.get(key)
+  }
+
+  fn insert(&mut self, key: CacheKey, data: CacheData, credibility: Credibility, ttl: Duration) {
+    let now = Instant::now();
+    if self.entries.get(&key).is_some_and(|entry| entry.expires > now && entry.credibility > credibility) {
+      return;
+    }
+
+    self.remove(&key);
+
+    while self.entries.len() >= self.max_size {
+      match self.lru.pop_first() {
+        Some((_, oldest)) => {
+          self.entries.remove(&oldest);
+        }
+        None => return,
+      }
+    }
+
+    self.tick += 1;
+    self.lru.insert(self.tick, key.clone());
+    self.entries.insert(key, CacheEntry {
+      data,
+      credibility,
+      expires: now + ttl,
+      last_used: self.tick,
+    });
+  }
+
+  fn touch(&mut self, key: &CacheKey) {
+    self.tick += 1;
+
+    if let Some(entry) = self.entries.get_mut(key) {
+      self.lru.remove(&entry.last_used);
+      entry.last_used = self.tick;
+      self.lru.insert(self.tick, key.clone());
+    }
+  }
+
+  fn remove(&mut self, key: &CacheKey) {
+    if let Some(entry) = self.entries.remove(key) {
+      self.lru.remove(&entry.last_used);
+    }
+  }
+}
+
+impl Default for Cache {
+  fn default() -> Self {
+    Self::new(DEFAULT_CACHE_SIZE)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::net::Ipv4Addr;
+
+  use super::*;
+  use crate::test_util::{a_record, aaaa_record, cname_record, name, ns_record};
+
+  #[test]
+  fn glue_does_not_replace_an_answer() {
+    let mut cache = Cache::default();
+    cache.store(&[a_record("ns.example.com", 1)], Credibility::Answer);
+    cache.store(&[a_record("ns.example.com", 66)], Credibility::Additional);
+    cache.store(&[a_record("ns.example.com", 66)], Credibility::Authority);
+
+    let records = cache.lookup(&name("ns.example.com"), QueryType::A, QueryClass::IN).unwrap();
+    assert_eq!(records.len(), 1);
+    assert!(matches!(records[0], DnsRecord::A { addr, .. } if addr == Ipv4Addr::new(192, 0, 2, 1)));
+  }
+
+  #[test]
+  fn answer_replaces_glue() {
+    let mut cache = Cache::default();
+    cache.store(&[a_record("ns.example.com", 66)], Credibility::Additional);
+    cache.store(&[a_record("ns.example.com", 1)], Credibility::Answer);
+
+    let records = cache.lookup(&name("ns.example.com"), QueryType::A, QueryClass::IN).unwrap();
+    assert!(matches!(records[0], DnsRecord::A { addr, .. } if addr == Ipv4Addr::new(192, 0, 2, 1)));
+  }
+
+  #[test]
+  fn glue_and_referrals_are_not_given_out_as_answers() {
+    let mut cache = Cache::default();
+    cache.store(&[ns_record("example.com", "ns1.example.com")], Credibility::Authority);
+    cache.store(&[a_record("ns1.example.com", 1)], Credibility::Additional);
+
+    assert!(cache.lookup_answer(&name("ns1.example.com"), QueryType::A, QueryClass::IN).is_none());
+    assert!(cache.lookup_answer(&name("example.com"), QueryType::NS, QueryClass::IN).is_none());
+    // They still point the way to the zone's servers
+    assert!(cache.closest_name_server(&name("www.example.com")).is_some());
+
+    cache.store(&[a_record("ns1.example.com", 1)], Credibility::Answer);
+    let answer = cache.lookup_answer(&name("ns1.example.com"), QueryType::A, QueryClass::IN).unwrap();
+    assert_eq!(answer.answers.len(), 1);
+  }
+
+  fn soa(domain: &str) -> DnsRecord {
+    DnsRecord::SOA {
+      domain: name(domain),
+      mname: name("ns.other"),
+      rname: name("hostmaster.other"),
+      serial: 1,
+      refresh: 3600,
+      retry: 600,
+      expire: 86400,
+      minimum: 300,
+      class: QueryClass::IN,
+      ttl: 300,
+    }
+  }
+
+  #[test]
+  fn nxdomain_behind_a_cname_is_stored_for_the_target() {
+    let mut response = DnsPacket::new();
+    response.header.rescode = ResultCode::NXDOMIAN;
+    response.answers.push(cname_record("alias.example", "gone.other"));
+    response.authorities.push(soa("other"));
+
+    let mut cache = Cache::default();
+    cache.store_negative(&name("alias.example"), QueryType::A, QueryClass::IN, &response);
+
+    // The alias exists, only its target does not
+    assert!(cache.lookup(&name("alias.example"), QueryType::CNAME, QueryClass::IN).is_some());
+    let answer = cache.lookup_answer(&name("alias.example"), QueryType::A, QueryClass::IN).unwrap();
+    assert_eq!(answer.header.rescode, ResultCode::NXDOMIAN);
+    assert_eq!(answer.answers.len(), 1);
+
+    let answer = cache.lookup_answer(&name("alias.example"), QueryType::TXT, QueryClass::IN).unwrap();
+    assert_eq!(answer.header.rescode, ResultCode::NXDOMIAN);
+
+    let answer = cache.lookup_answer(&name("gone.other"), QueryType::A, QueryClass::IN).unwrap();
+    assert_eq!(answer.header.rescode, ResultCode::NXDOMIAN);
+    assert!(answer.answers.is_empty());
+  }
+
+  #[test]
+  fn nodata_behind_a_cname_is_stored_for_the_target() {
+    let mut response = DnsPacket::new();
+    response.answers.push(cname_record("alias.example", "host.other"));
+    response.authorities.push(soa("other"));
+
+    let mut cache = Cache::default();
+    cache.store_negative(&name("alias.example"), QueryType::AAAA, QueryClass::IN, &response);
+
+    let answer = cache.lookup_answer(&name("host.other"), QueryType::AAAA, QueryClass::IN).unwrap();
+    assert_eq!(answer.header.rescode, ResultCode::NOERROR);
+    assert!(answer.answers.is_empty());
+    assert!(cache.lookup_answer(&name("alias.example"), QueryType::A, QueryClass::IN).is_none());
+  }
+
+  #[test]
+  fn closest_name_server_gives_every_address() {
+    let mut cache = Cache::default();
+    cache.store(&[
+      ns_record("example.com", "ns1.example.com"),
+      ns_record("example.com", "ns2.example.com"),
+    ], Credibility::Authority);
+    cache.store(&[
+      a_record("ns1.example.com", 1),
+      aaaa_record("ns1.example.com", 1),
+      a_record("ns2.example.com", 2),
+    ], Credibility::Additional);
+
+    let (zone, addrs) = cache.closest_name_server(&name("www.example.com")).unwrap();
+    assert_eq!(zone, name("example.com"));
+    assert_eq!(addrs.len(), 3);
+    assert!(addrs.contains(&"2001:db8::1".parse::<IpAddr>().unwrap()));
+  }
+}
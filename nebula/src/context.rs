@@ -0,0 +1,32 @@
+use std::{net::IpAddr, sync::Mutex};
+
+use crate::{authority::Authority, cache::{Cache, DEFAULT_CACHE_SIZE}, identity::Identity};
+
+// State shared by every UDP and TCP handler
+pub struct ServerContext {
+  pub cache: Mutex<Cache>,
+  pub authority: Authority,
+  pub identity: Identity,
+  // Send every query to this resolver instead of resolving from the root servers ourselves
+  pub forwarder: Option<IpAddr>,
+  // Log names with Unicode labels instead of A-labels
+  pub unicode_names: bool,
+}
+
+impl ServerContext {
+  pub fn new() -> Self {
+    Self {
+      cache: Mutex::new(Cache::new(DEFAULT_CACHE_SIZE)),
+      authority: Authority::new(),
+      identity: Identity::new(),
+      forwarder: None,
+      unicode_names: false,
+    }
+  }
+}
+
+impl Default for ServerContext {
+  fn default() -> Self {
+    Self::new()
+  }
+}
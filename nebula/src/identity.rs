@@ -0,0 +1,126 @@
+use crate::protocol::{dns_packet::DnsPacket, dns_record::DnsRecord, ede::{ExtendedError, ExtendedErrorCode}, edns::Edns, name::Name, query_class::QueryClass, query_type::QueryType, res_code::ResultCode};
+
+// Names that report the software version (RFC 4892 section 2)
+const VERSION_NAMES: [&str; 2] = ["version.bind", "version.server"];
+// Names that identify the instance answering (RFC 4892 section 2)
+const HOSTNAME_NAMES: [&str; 2] = ["hostname.bind", "id.server"];
+
+// What Nebula tells clients about itself through CHAOS class TXT queries
+pub struct Identity {
+  pub enabled: bool,
+  pub version: Option<String>,
+  pub hostname: Option<String>,
+}
+
+fn is_one_of(qname: &Name, names: &[&str]) -> bool {
+  names.iter().any(|name| Name::parse(name).is_ok_and(|name| name == *qname))
+}
+
+impl Identity {
+  pub fn new() -> Self {
+    Self {
+      enabled: true,
+      version: Some(format!("nebula {}", env!("CARGO_PKG_VERSION"))),
+      hostname: None,
+    }
+  }
+
+  // Answer a CH class identity query. Other questions give `None`, identity names that are
+  // disabled or not configured are refused.
+  pub fn query(&self, qname: &Name, qtype: QueryType, qclass: QueryClass) -> Option<DnsPacket> {
+    if qclass != QueryClass::CH {
+      return None;
+    }
+
+    let value = if is_one_of(qname, &VERSION_NAMES) {
+      &self.version
+    } else if is_one_of(qname, &HOSTNAME_NAMES) {
+      &self.hostname
+    } else {
+      return None;
+    };
+
+    let mut packet = DnsPacket::new();
+
+    let value = match value {
+      Some(value) if self.enabled => value,
+      _ => {
+        let reason = if self.enabled { "No value is configured for this identity name" } else { "Identity queries are disabled" };
+        packet.header.rescode = ResultCode::REFUSED;
+        packet.edns = Some(Edns::new());
+        packet.add_extended_error(ExtendedError::new(ExtendedErrorCode::Prohibited, reason));
+        return Some(packet);
+      }
+    };
+
+    packet.header.authoritative_answer = true;
+    if qtype == QueryType::TXT {
+      packet.answers.push(DnsRecord::TXT {
+        domain: qname.clone(),
+        data: vec![value.as_bytes().to_vec()],
+        class: QueryClass::CH,
+        ttl: 0,
+      });
+    }
+
+    Some(packet)
+  }
+}
+
+impl Default for Identity {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util::name;
+
+  fn refusal_text(packet: &DnsPacket) -> String {
+    assert_eq!(packet.header.rescode, ResultCode::REFUSED);
+    packet.edns.as_ref().unwrap().extended_errors()[0].text.clone()
+  }
+
+  #[test]
+  fn chaos_txt_query_is_answered() {
+    let mut identity = Identity::new();
+    identity.hostname = Some("ns1.example".to_string());
+
+    let packet = identity.query(&name("hostname.bind"), QueryType::TXT, QueryClass::CH).unwrap();
+    assert_eq!(packet.header.rescode, ResultCode::NOERROR);
+    assert!(packet.header.authoritative_answer);
+    assert_eq!(packet.answers, vec![DnsRecord::TXT { domain: name("hostname.bind"), data: vec![b"ns1.example".to_vec()], class: QueryClass::CH, ttl: 0 }]);
+
+    let packet = identity.query(&name("VERSION.SERVER"), QueryType::TXT, QueryClass::CH).unwrap();
+    assert!(matches!(&packet.answers[0], DnsRecord::TXT { data, .. } if data[0].starts_with(b"nebula ")));
+  }
+
+  #[test]
+  fn disabled_identity_is_refused() {
+    let mut identity = Identity::new();
+    identity.enabled = false;
+    identity.hostname = Some("ns1.example".to_string());
+
+    let packet = identity.query(&name("version.bind"), QueryType::TXT, QueryClass::CH).unwrap();
+    assert_eq!(refusal_text(&packet), "Identity queries are disabled");
+    assert!(packet.answers.is_empty());
+
+    let packet = identity.query(&name("id.server"), QueryType::TXT, QueryClass::CH).unwrap();
+    assert_eq!(refusal_text(&packet), "Identity queries are disabled");
+  }
+
+  #[test]
+  fn unconfigured_hostname_is_refused_as_such() {
+    let packet = Identity::new().query(&name("hostname.bind"), QueryType::TXT, QueryClass::CH).unwrap();
+    assert_eq!(refusal_text(&packet), "No value is configured for this identity name");
+  }
+
+  #[test]
+  fn other_classes_and_names_are_left_alone() {
+    let identity = Identity::new();
+    assert!(identity.query(&name("version.bind"), QueryType::TXT, QueryClass::IN).is_none());
+    assert!(identity.query(&name("www.example.com"), QueryType::TXT, QueryClass::CH).is_none());
+  }
+}
@@ -0,0 +1,11 @@
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod authority;
+pub mod cache;
+pub mod context;
+pub mod identity;
+pub mod protocol;
+pub mod transport;
+
+#[cfg(test)]
+mod test_util;
@@ -1,86 +1,276 @@
-use std::net::{IpAddr, Ipv4Addr, UdpSocket};
+use std::{collections::VecDeque, env, io::ErrorKind, path::Path, net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream, UdpSocket}, sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex}, thread, time::{Duration, Instant}};
 
-use protocol::{byte_packet::BytePacketBuffer, dns_packet::DnsPacket, dns_question::DnsQuestion, query_type::QueryType, res_code::ResultCode};
+use nebula::{cache::{Cache, Credibility}, context::ServerContext, protocol::{byte_packet::{BytePacketBuffer, PacketBuffer, MAX_EDNS_PACKET_SIZE, MAX_TCP_PACKET_SIZE, MAX_UDP_PACKET_SIZE}, dns_header::DnsHeader, dns_packet::{DnsPacket, MAX_CNAME_CHAIN}, dns_question::DnsQuestion, dns_record::DnsRecord, ede::{ExtendedError, ExtendedErrorCode}, edns::Edns, error::ProtocolError, name::Name, opcode::Opcode, query_class::QueryClass, query_type::QueryType, res_code::ResultCode}, transport::{read_tcp_message, write_tcp_message}};
 
-use crate::protocol::response_packet::response_packet;
+// How long a client TCP connection may sit idle between two queries
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+// Most client TCP connections served at once, each one holds a thread
+const MAX_TCP_CONNECTIONS: usize = 128;
+// How deeply lookups of name server addresses may nest, e.g. for a name server whose name lies
+// inside the zone it serves and that came without glue
+const MAX_LOOKUP_DEPTH: usize = 6;
+// How long we wait on an upstream server over UDP before trying the next one
+const UPSTREAM_UDP_TIMEOUT: Duration = Duration::from_secs(2);
+// How long we wait on an upstream server over TCP
+const UPSTREAM_TCP_TIMEOUT: Duration = Duration::from_secs(5);
 
 
-mod protocol;
+// Query IDs come from the OS random number generator so they can not be guessed by a spoofer
+fn build_query(qname: &Name, qtype: QueryType) -> Result<DnsPacket, Box<dyn std::error::Error>> {
+  let mut id = [0u8; 2];
+  getrandom::fill(&mut id).map_err(|e| format!("Could not generate a query ID: {}", e))?;
 
+  let mut packet = DnsPacket::new();
 
+  packet.header.id = u16::from_be_bytes(id);
+  packet.header.questions = 1;
+  packet.header.recursion_desired = true;
 
-fn look_up(qname: &str, qtype: QueryType, server: (Ipv4Addr, u16)) -> Result<DnsPacket, Box<dyn std::error::Error>> {
-  // let qname = "google.com";
-  // let qtype = QueryType::A;
+  packet.questions.push(DnsQuestion::new(qname.clone(), qtype));
+  packet.edns = Some(Edns::new());
 
-  let server = ("8.8.8.8", 53);
+  Ok(packet)
+}
 
-  let socket = UdpSocket::bind(("0.0.0.0", 43210))?;
+// Whether a response is the answer to our query, so stray or forged packets are not taken for it
+fn answers_query(query: &DnsPacket, response: &DnsPacket) -> bool {
+  response.header.response
+    && response.header.id == query.header.id
+    && response.questions == query.questions
+}
 
-  let mut packet = DnsPacket::new();
+fn look_up(qname: &Name, qtype: QueryType, server: (IpAddr, u16)) -> Result<DnsPacket, Box<dyn std::error::Error>> {
+  // Port 0 has the OS pick a fresh, randomized ephemeral port for every query
+  let local: IpAddr = if server.0.is_ipv4() { Ipv4Addr::UNSPECIFIED.into() } else { Ipv6Addr::UNSPECIFIED.into() };
+  let socket = UdpSocket::bind((local, 0))?;
 
-  packet.header.id = 6666;
-  packet.header.questions = 1;
-  packet.header.recursion_desired = true;
+  let mut packet = build_query(qname, qtype)?;
 
-  packet.questions.push(DnsQuestion::new(qname.to_string(), qtype));
+  let mut req_buffer = BytePacketBuffer::new();
+  packet.write(&mut req_buffer)?;
+  socket.send_to(req_buffer.filled(), server)?;
+
+  // Anything that is not our answer is dropped and we keep waiting, so a spoofer has to hit the
+  // right ID and port before the real answer arrives instead of just being first
+  let deadline = Instant::now() + UPSTREAM_UDP_TIMEOUT;
+  let response = loop {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+      return Err(format!("No response from {} in time", SocketAddr::from(server)).into());
+    }
+    socket.set_read_timeout(Some(remaining))?;
+
+    let mut res_buffer = BytePacketBuffer::with_size(MAX_EDNS_PACKET_SIZE);
+    let (length, source) = socket.recv_from(&mut res_buffer.buf)?;
+    res_buffer.buf.truncate(length);
+
+    if source != SocketAddr::from(server) {
+      println!("Discarding datagram from {}, expected {}", source, SocketAddr::from(server));
+      continue;
+    }
+
+    match DnsPacket::from_buffer(&mut res_buffer) {
+      Ok(response) if answers_query(&packet, &response) => break response,
+      Ok(_) => println!("Discarding response from {} that does not match the query", source),
+      Err(e) => println!("Discarding malformed response from {}: {}", source, e),
+    }
+  };
+
+  // The answer did not fit in a datagram, ask again over TCP to get all of it
+  if response.header.truncated_message {
+    return look_up_tcp(qname, qtype, server);
+  }
+
+  Ok(response)
+}
+
+fn look_up_tcp(qname: &Name, qtype: QueryType, server: (IpAddr, u16)) -> Result<DnsPacket, Box<dyn std::error::Error>> {
+  let mut stream = TcpStream::connect_timeout(&SocketAddr::from(server), UPSTREAM_TCP_TIMEOUT)?;
+  stream.set_read_timeout(Some(UPSTREAM_TCP_TIMEOUT))?;
+  stream.set_write_timeout(Some(UPSTREAM_TCP_TIMEOUT))?;
+
+  let mut packet = build_query(qname, qtype)?;
 
   let mut req_buffer = BytePacketBuffer::new();
-  let _ = packet.write(&mut req_buffer);
-  socket.send_to(&req_buffer.buf[0..req_buffer.pos], server)?;
+  packet.write(&mut req_buffer)?;
+  write_tcp_message(&mut stream, req_buffer.filled())?;
 
-  let mut res_buffer = BytePacketBuffer::new();
-  socket.recv_from(&mut res_buffer.buf)?;
+  let response = match read_tcp_message(&mut stream)? {
+    Some(mut res_buffer) => DnsPacket::from_buffer(&mut res_buffer)?,
+    None => return Err("Connection closed before a response was received".into()),
+  };
 
-  DnsPacket::from_buffer(&mut res_buffer)
+  if !answers_query(&packet, &response) {
+    return Err(format!("Response from {} does not match the query", SocketAddr::from(server)).into());
+  }
 
+  Ok(response)
 }
 
-fn handle_query(socket: &UdpSocket) -> Result<(), Box<dyn std::error::Error>> {
-  let mut req_buffer =BytePacketBuffer::new();
+// Ask each server in turn until one of them answers. IPv4 goes first, as IPv6 connectivity is
+// the one more often missing. SERVFAIL, REFUSED and NOTIMP come from lame or broken servers, so
+// they count as failures too.
+fn look_up_any(qname: &Name, qtype: QueryType, servers: &[IpAddr]) -> Result<DnsPacket, Box<dyn std::error::Error>> {
+  let mut servers = servers.to_vec();
+  servers.sort_by_key(|addr| addr.is_ipv6());
+
+  let mut last_error = None;
+  for server in servers {
+    println!("Attempting lookup for {:?} {} for ns {}", qtype, qname, server);
+
+    match look_up(qname, qtype, (server, 53)) {
+      Ok(response) if matches!(response.header.rescode, ResultCode::SERVFAIL | ResultCode::REFUSED | ResultCode::NOTIMP) => {
+        println!("Lookup at {} failed: {:?}", server, response.header.rescode);
+        last_error = Some(format!("{} answered {:?}", server, response.header.rescode).into());
+      }
+      Ok(response) => return Ok(response),
+      Err(e) => {
+        println!("Lookup at {} failed: {}", server, e);
+        last_error = Some(e);
+      }
+    }
+  }
 
-  let(_, src) = socket.recv_from(&mut req_buffer.buf)?;
+  Err(last_error.unwrap_or_else(|| "No name server to ask".into()))
+}
 
-  let mut request = DnsPacket::from_buffer(&mut req_buffer)?;
+// A record as it is logged, with Unicode names when asked for
+fn display_record(context: &ServerContext, rec: &DnsRecord) -> String {
+  if context.unicode_names {
+    format!("{:#}", rec)
+  } else {
+    rec.to_string()
+  }
+}
 
+// Resolve the request and build the response packet, independent of the transport it came in on
+fn build_response(context: &ServerContext, mut request: DnsPacket) -> DnsPacket {
   let mut packet =DnsPacket::new();
   packet.header.id = request.header.id;
   packet.header.recursion_desired = true;
-  packet.header.recursion_desired = true;
   packet.header.response = true;
+  packet.header.opcode = request.header.opcode;
 
+  // Answer with our own OPT record whenever the client spoke EDNS
+  if let Some(edns) = &request.edns {
+    packet.edns = Some(Edns::new());
 
-  if let Some(question) = request.questions.pop() {
-    println!("Received query {:?}", question);
+    // We only speak EDNS version 0 (RFC 6891 section 6.1.3)
+    if edns.version > 0 {
+      packet.header.rescode = ResultCode::BADVERS;
+      return packet;
+    }
+  }
 
-    if let Ok(result) = recursive_lookup(&question.name, question.qtype) {
-    packet.questions.push(question.clone());
-    packet.header.rescode = result.header.rescode;
+  // Only standard queries are answered, NOTIFY, UPDATE and the rest are not implemented
+  if request.header.opcode != Opcode::QUERY {
+    packet.header.rescode = ResultCode::NOTIMP;
+    return packet;
+  }
 
-    for rec in result.answers {
-      println!("Answer: {:?}", rec);
-      packet.answers.push(rec);
-    }
+  if let Some(question) = request.questions.pop() {
+    println!("Received query {:?}", question);
 
-    for rec in result.authorities {
-      println!("Authorities: {:?}", rec);
-      packet.authorities.push(rec);
-    }
+    // Local data takes precedence over anything we would resolve
+    let local = context.identity.query(&question.name, question.qtype, question.qclass)
+      .or_else(|| context.authority.query(&question.name, question.qtype, question.qclass));
+
+    let result = match local {
+      Some(local) => Ok(local),
+      // Only the IN class is resolved, other classes can only be answered from local data
+      None if question.qclass != QueryClass::IN => {
+        let mut refused = DnsPacket::new();
+        refused.header.rescode = ResultCode::REFUSED;
+        refused.edns = Some(Edns::new());
+        refused.add_extended_error(ExtendedError::new(ExtendedErrorCode::NotSupported, "Only class IN is resolved"));
+        Ok(refused)
+      }
+      None => recursive_lookup(context, &question.name, question.qtype),
+    };
 
-    for rec in result.resources {
-      println!("Resources: {:?}", rec);
-      packet.resources.push(rec);
-    }
-    } else {
-      packet.header.rescode = ResultCode::SERVFAIL
+    match result {
+      Ok(result) => {
+        packet.questions.push(question.clone());
+        packet.header.rescode = result.header.rescode;
+        // An upstream BADCOOKIE or BADVERS can only be told with an OPT record, which a client
+        // that did not speak EDNS may not be sent (RFC 6891 section 7)
+        if packet.edns.is_none() && packet.header.rescode.to_num() > 0x0F {
+          packet.header.rescode = ResultCode::SERVFAIL;
+        }
+        packet.header.authoritative_answer = result.header.authoritative_answer;
+
+        for rec in result.answers {
+          println!("Answer: {}", display_record(context, &rec));
+          packet.answers.push(rec);
+        }
+
+        for rec in result.authorities {
+          println!("Authorities: {}", display_record(context, &rec));
+          packet.authorities.push(rec);
+        }
+
+        for rec in result.resources {
+          println!("Resources: {}", display_record(context, &rec));
+          packet.resources.push(rec);
+        }
+
+        // Pass on any explanation of the outcome, ours or the upstream server's
+        for error in result.edns.iter().flat_map(|edns| edns.extended_errors()) {
+          println!("Extended error: {}", error);
+          packet.add_extended_error(error);
+        }
+      }
+      Err(e) => {
+        packet.header.rescode = ResultCode::SERVFAIL;
+        packet.add_extended_error(ExtendedError::new(ExtendedErrorCode::NoReachableAuthority, &e.to_string()));
+      }
     }
   }
   else {
     packet.header.rescode = ResultCode::FORMERR
   }
 
-  let mut res_buffer = BytePacketBuffer::new();
-  packet.write(&mut res_buffer)?;
+  packet
+}
+
+// Answer a raw request, along with the largest UDP response its sender accepts. Malformed
+// requests get FORMERR, unlike failures to resolve which get SERVFAIL. Requests too short to
+// hold a header give an error instead, as there is no ID to answer to.
+fn answer_request(context: &ServerContext, req_buffer: &mut BytePacketBuffer) -> Result<(DnsPacket, usize), ProtocolError> {
+  let request = match DnsPacket::from_buffer(req_buffer) {
+    Ok(request) => request,
+    Err(e) => {
+      println!("Malformed query: {}", e);
+
+      req_buffer.change_buffer_position(0)?;
+      let mut header = DnsHeader::new();
+      header.read(req_buffer)?;
+
+      let mut packet = DnsPacket::new();
+      packet.header.id = header.id;
+      packet.header.opcode = header.opcode;
+      packet.header.recursion_desired = header.recursion_desired;
+      packet.header.response = true;
+      packet.header.rescode = ResultCode::FORMERR;
+
+      return Ok((packet, MAX_UDP_PACKET_SIZE));
+    }
+  };
+
+  let max_size = request.max_udp_size();
+  Ok((build_response(context, request), max_size))
+}
+
+fn handle_query(context: &ServerContext, socket: &UdpSocket) -> Result<(), Box<dyn std::error::Error>> {
+  let mut req_buffer = BytePacketBuffer::with_size(MAX_EDNS_PACKET_SIZE);
+
+  let (length, src) = socket.recv_from(&mut req_buffer.buf)?;
+  req_buffer.buf.truncate(length);
+
+  let (mut packet, max_size) = answer_request(context, &mut req_buffer)?;
+
+  let mut res_buffer = BytePacketBuffer::with_size(max_size);
+  packet.write_truncated(&mut res_buffer, max_size)?;
 
   let length = res_buffer.pos;
   let data = res_buffer.get_range(0, length)?;
@@ -90,55 +280,315 @@ fn handle_query(socket: &UdpSocket) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-pub fn recursive_lookup(qname: &str, qtype: QueryType) -> Result<DnsPacket, Box<dyn std::error::Error>> {
-  let mut ns = "198.41.0.4".parse::<Ipv4Addr>().unwrap();
+// Serve every query sent on a TCP connection, in order, until the client closes it or goes idle
+fn handle_tcp_connection(context: &ServerContext, mut stream: TcpStream) -> Result<(), Box<dyn std::error::Error>> {
+  stream.set_read_timeout(Some(TCP_IDLE_TIMEOUT))?;
 
   loop {
-    println!("Attempting lookup for {:?} {} for ns {}", qtype, qname, ns);
+    let mut req_buffer = match read_tcp_message(&mut stream) {
+      Ok(Some(buffer)) => buffer,
+      Ok(None) => break,
+      // The client went idle, close the connection
+      Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => break,
+      Err(e) => return Err(e.into()),
+    };
+
+    let (mut packet, _) = answer_request(context, &mut req_buffer)?;
 
+    let mut res_buffer = BytePacketBuffer::with_size(MAX_TCP_PACKET_SIZE);
+    packet.write_truncated(&mut res_buffer, MAX_TCP_PACKET_SIZE)?;
 
-    let ns_copy = ns;
-    let server = (ns_copy, 53);
-    let response = look_up(qname, qtype, server)?;
+    write_tcp_message(&mut stream, res_buffer.filled())?;
+  }
+
+  Ok(())
+}
+
+// Gives back a TCP connection slot when its thread is done with it, however it ends
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl Drop for ConnectionSlot {
+  fn drop(&mut self) {
+    self.0.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+// Connections past MAX_TCP_CONNECTIONS are closed straight away, so idle clients can not use up
+// every thread; the client can retry once a slot frees up
+fn serve_tcp(context: Arc<ServerContext>, listener: TcpListener) {
+  let open = Arc::new(AtomicUsize::new(0));
+
+  for stream in listener.incoming() {
+    match stream {
+      Ok(stream) => {
+        if open.fetch_add(1, Ordering::SeqCst) >= MAX_TCP_CONNECTIONS {
+          open.fetch_sub(1, Ordering::SeqCst);
+          eprintln!("Refusing TCP connection, {} already open", MAX_TCP_CONNECTIONS);
+          continue;
+        }
+
+        let slot = ConnectionSlot(Arc::clone(&open));
+        let context = Arc::clone(&context);
+        thread::spawn(move || {
+          let _slot = slot;
+          if let Err(e) = handle_tcp_connection(&context, stream) {
+            eprintln!("An error occured on a TCP connection: {}", e)
+          }
+        });
+      }
+      Err(e) => eprintln!("An error occured accepting a TCP connection: {}", e)
+    }
+  }
+}
+
+pub fn recursive_lookup(context: &ServerContext, qname: &Name, qtype: QueryType) -> Result<DnsPacket, Box<dyn std::error::Error>> {
+  resolve(context, qname, qtype, 0)
+}
+
+// `depth` counts the name server lookups this one is nested in
+fn resolve(context: &ServerContext, qname: &Name, qtype: QueryType, depth: usize) -> Result<DnsPacket, Box<dyn std::error::Error>> {
+  let mut response = follow_referrals(context, qname, qtype, depth)?;
+
+  // The servers of a zone only answer for their own names, so an alias into another zone comes
+  // back as just its CNAME. Carry on from where the chain ends and hand back all of it together
+  // with the answer for the target (RFC 1034 section 4.3.2).
+  for _ in 0..MAX_CNAME_CHAIN {
+    if qtype == QueryType::CNAME || response.header.rescode != ResultCode::NOERROR {
+      break;
+    }
+
+    let (target, chain) = response.get_cname_chain(qname);
+    if chain.is_empty() || response.answers.iter().any(|rec| rec.query_type() == qtype && *rec.domain() == target) {
+      break;
+    }
+
+    println!("Following CNAME to {}", target);
+    let next = follow_referrals(context, &target, qtype, depth)?;
+    response.header.rescode = next.header.rescode;
+    response.answers.extend(next.answers);
+    response.authorities = next.authorities;
+    response.resources = next.resources;
+  }
+
+  Ok(response)
+}
+
+// Resolve a single name, walking down the referrals from the closest zone we know the servers of
+fn follow_referrals(context: &ServerContext, qname: &Name, qtype: QueryType, depth: usize) -> Result<DnsPacket, Box<dyn std::error::Error>> {
+  if depth > MAX_LOOKUP_DEPTH {
+    return Err(format!("Gave up on {} after {} nested name server lookups", qname, MAX_LOOKUP_DEPTH).into());
+  }
+
+  if let Some(packet) = context.cache.lock().unwrap().lookup_answer(qname, qtype, QueryClass::IN) {
+    println!("Cache hit for {:?} {}", qtype, qname);
+
+    return Ok(packet)
+  }
+
+  // A forwarder does the recursion for us, so it is trusted with every name
+  if let Some(forwarder) = context.forwarder {
+    let response = look_up_any(qname, qtype, &[forwarder])?;
+
+    let mut cache = context.cache.lock().unwrap();
+    if response.header.rescode == ResultCode::NOERROR && !response.answers.is_empty() {
+      cache.store(&response.answers, Credibility::Answer);
+    } else {
+      cache.store_negative(qname, qtype, QueryClass::IN, &response);
+    }
+
+    return Ok(response)
+  }
+
+  // Start at the closest zone cut we already know about instead of the root. `zone` is what the
+  // servers being asked are authoritative for, and so all they may tell us about.
+  let (mut zone, mut servers) = match context.cache.lock().unwrap().closest_name_server(qname) {
+    Some((zone, addrs)) => (zone, addrs),
+    None => (Name::root(), vec!["198.41.0.4".parse::<IpAddr>().unwrap()]),
+  };
+  // Name servers of `zone` without glue that have not been looked up yet
+  let mut spare_hosts = VecDeque::new();
+
+  loop {
+    let mut response = match look_up_any(qname, qtype, &servers) {
+      Ok(response) => response,
+      // Every address of this name server failed, try the next one of the zone
+      Err(e) => match next_name_server(context, &mut spare_hosts, depth) {
+        Some(addrs) => {
+          servers = addrs;
+          continue;
+        }
+        None => return Err(e),
+      },
+    };
+    response.remove_out_of_bailiwick(qname, &zone);
+
+    {
+      let mut cache = context.cache.lock().unwrap();
+      if response.header.rescode == ResultCode::NOERROR {
+        cache.store(&response.answers, Credibility::Answer);
+      }
+      // Delegations and their glue let later lookups skip straight to this zone
+      cache.store(&response.authorities, Credibility::Authority);
+      cache.store(&response.resources, Credibility::Additional);
+    }
 
     if !response.answers.is_empty() && response.header.rescode == ResultCode::NOERROR {
       return Ok(response)
     }
 
     if response.header.rescode == ResultCode::NXDOMIAN {
+      context.cache.lock().unwrap().store_negative(qname, qtype, QueryClass::IN, &response);
       return Ok(response);
     }
 
-    if let Some(new_ns) = response.get_resolved_ns(qname) {
-      ns = new_ns;
+    // Only a delegation further down is a referral, the zone naming its own servers is not
+    let cut = match response.get_zone_cut(qname) {
+      Some(cut) if *cut != zone => cut.clone(),
+      _ => {
+        // No answer and no referral, the name exists without records of this type
+        context.cache.lock().unwrap().store_negative(qname, qtype, QueryClass::IN, &response);
+        return Ok(response)
+      }
+    };
+
+    let resolved = response.get_resolved_ns(qname);
+    if !resolved.is_empty() {
+      zone = cut;
+      servers = resolved;
+      spare_hosts.clear();
 
       continue;
     }
 
-    let new_ns_name = match response.get_unresolved_ns(qname) {
-      Some(x) => x,
-      None => return Ok(response)
-    };
+    // No glue, look up the name servers ourselves. One that has addresses is enough to go on
+    // with, the others are only looked up if all of its addresses fail.
+    spare_hosts = response.get_unresolved_ns(qname).into_iter().cloned().collect();
+    servers = next_name_server(context, &mut spare_hosts, depth)
+      .ok_or_else(|| format!("No name server of {} could be found", cut))?;
+    zone = cut;
+  }
+}
 
-    let recursive_response = recursive_lookup(&new_ns_name, QueryType::A)?;
+// Look up the name servers in `hosts` in turn, taking each off the list, until one of them gives
+// an address. Every IPv4 and IPv6 address of that one is returned.
+fn next_name_server(context: &ServerContext, hosts: &mut VecDeque<Name>, depth: usize) -> Option<Vec<IpAddr>> {
+  while let Some(host) = hosts.pop_front() {
+    let mut addrs = Vec::new();
+    for ns_qtype in [QueryType::A, QueryType::AAAA] {
+      match resolve(context, &host, ns_qtype, depth + 1) {
+        Ok(ns_response) => addrs.extend(ns_response.get_addrs()),
+        Err(e) => println!("Lookup of name server {} failed: {}", host, e),
+      }
+    }
+    addrs.sort();
+    addrs.dedup();
 
-    if let Some(new_ns) = recursive_response.get_random_a() {
-      ns = new_ns;
-    } else {
-      return Ok(response)
+    if !addrs.is_empty() {
+      return Some(addrs);
     }
   }
+
+  None
+}
+
+// Build the shared server state from the command line flags
+fn parse_args() -> Result<ServerContext, Box<dyn std::error::Error>> {
+  let mut context = ServerContext::new();
+  let mut args = env::args().skip(1);
+  let mut reverse_zone = false;
+
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--cache-size" => {
+        let size = args.next().ok_or("--cache-size needs a value")?.parse::<usize>()?;
+        context.cache = Mutex::new(Cache::new(size));
+      }
+      "--zone" => {
+        let path = args.next().ok_or("--zone needs a file")?;
+        context.authority.load_zone_file(Path::new(&path))?;
+      }
+      "--reverse-zone" => reverse_zone = true,
+      "--version-string" => {
+        context.identity.version = Some(args.next().ok_or("--version-string needs a value")?);
+      }
+      "--hide-version" => context.identity.version = None,
+      "--hostname" => {
+        context.identity.hostname = Some(args.next().ok_or("--hostname needs a value")?);
+      }
+      "--no-identity" => context.identity.enabled = false,
+      "--unicode" => context.unicode_names = true,
+      "--forward" => {
+        context.forwarder = Some(args.next().ok_or("--forward needs an address")?.parse::<IpAddr>()?);
+      }
+      _ => return Err(format!("Unknown argument {}", arg).into()),
+    }
+  }
+
+  // Generated last so it covers the records of every zone file
+  if reverse_zone {
+    context.authority.add_reverse_zone();
+  }
+
+  Ok(context)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+  let context = Arc::new(parse_args()?);
+
   let socket = UdpSocket::bind(("0.0.0.0", 2053))?;
+  let listener = TcpListener::bind(("0.0.0.0", 2053))?;
+
+  let tcp_context = Arc::clone(&context);
+  thread::spawn(move || serve_tcp(tcp_context, listener));
 
-  println!("Server running on PORT: 2053");
+  println!("Server running on PORT: 2053 (UDP and TCP)");
 
   loop {
-    match handle_query(&socket) {
+    match handle_query(&context, &socket) {
       Ok(_) => {},
       Err(e) => eprintln!("An error occured: {}", e)
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn query_and_response() -> (DnsPacket, DnsPacket) {
+    let query = build_query(&Name::parse("www.example.com").unwrap(), QueryType::A).unwrap();
+    let mut response = query.clone();
+    response.header.response = true;
+    (query, response)
+  }
+
+  #[test]
+  fn matching_response_answers_the_query() {
+    let (query, response) = query_and_response();
+    assert!(answers_query(&query, &response));
+  }
+
+  #[test]
+  fn response_with_the_wrong_id_is_rejected() {
+    let (query, mut response) = query_and_response();
+    response.header.id = query.header.id.wrapping_add(1);
+    assert!(!answers_query(&query, &response));
+  }
+
+  #[test]
+  fn response_to_another_question_is_rejected() {
+    let (query, mut response) = query_and_response();
+    response.questions[0] = DnsQuestion::new(Name::parse("www.example.org").unwrap(), QueryType::A);
+    assert!(!answers_query(&query, &response));
+
+    response.questions[0] = DnsQuestion::new(Name::parse("www.example.com").unwrap(), QueryType::AAAA);
+    assert!(!answers_query(&query, &response));
+  }
+
+  #[test]
+  fn response_without_the_qr_bit_is_rejected() {
+    let (query, mut response) = query_and_response();
+    response.header.response = false;
+    assert!(!answers_query(&query, &response));
+  }
+}
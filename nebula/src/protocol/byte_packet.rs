@@ -1,98 +1,89 @@
-use std::usize;
+use std::collections::HashMap;
 
-type Error = Box<dyn std::error::Error>;
-type Result<T> = std::result::Result<T, Error>;
+use super::{error::ProtocolError, name::{Name, MAX_LABEL_LENGTH, MAX_NAME_LENGTH}};
 
+type Result<T> = std::result::Result<T, ProtocolError>;
 
+// Classic DNS over UDP limit (RFC 1035)
+pub const MAX_UDP_PACKET_SIZE: usize = 512;
+// Largest UDP payload we are willing to advertise or accept with EDNS
+pub const MAX_EDNS_PACKET_SIZE: usize = 4096;
+// Largest message the two byte TCP length prefix can describe
+pub const MAX_TCP_PACKET_SIZE: usize = 65535;
+// Compression pointers only have 14 bits for the offset
+const MAX_POINTER_OFFSET: usize = 0x3FFF;
 
 
-pub struct BytePacketBuffer {
-  pub buf: [u8; 512],
-  pub pos: usize,
-}
-
-
-
-impl BytePacketBuffer {
-  
-  // This give a fresh buffer for holding the packet contents and field for tracking where things are
-  pub fn new () -> Self {
-    Self { buf: [0; 512], pos: 0, }
-  } 
-
-    // The current position with the buffer
-    pub fn current_positon_in_buffer(&self) -> usize {
-      self.pos
+// Everything needed to read and write DNS messages. Implementors only provide the raw byte
+// access, the multi byte integers and names are built on top of it.
+pub trait PacketBuffer {
+  // The current position with the buffer
+  fn current_positon_in_buffer(&self) -> usize;
 
-    }
-
-    // Moving the buffer a step forward with a specific number of steps
-    pub fn move_buffer(&mut self, moves:usize) -> Result<()> {
-      self.pos += moves;
+  // Moving the buffer a step forward with a specific number of steps
+  fn move_buffer(&mut self, moves: usize) -> Result<()>;
 
-      Ok(())
-    }
+  // Change the buffer position
+  fn change_buffer_position(&mut self, pos: usize) -> Result<()>;
 
-    // Change the buffer position
-    pub fn change_buffer_position(&mut self, pos: usize) -> Result<()> {
-      self.pos = pos;
+  // Get a single byte, without changing position
+  fn get_single_byte(&mut self, pos: usize) -> Result<u8>;
 
-      Ok(())
-    }
+  // Get range of byte
+  fn get_range(&mut self, start: usize, length: usize) -> Result<&[u8]>;
 
-    // Read a single byte and move the position a single step forward
-    pub fn read_single_byte(&mut self) -> Result<u8> {
-      
-      if self.pos >= 512 {
-        return Err("End of Buffer".into())
-      }
+  // Write a single byte to the buffer and advance the position
+  fn write(&mut self, val: u8) -> Result<()>;
 
-      let res = self.buf[self.pos];
-      self.pos += 1;
+  // Overwrite a previously written byte
+  fn set(&mut self, pos: usize, val: u8) -> Result<()>;
 
-      Ok(res)
-  }
+  // Where a name (or name suffix) was previously written, for compression
+  fn find_label(&self, labels: &[Vec<u8>]) -> Option<usize>;
 
-  // Get a single byte, without changing position
-  pub fn get_single_byte(&mut self, pos: usize) -> Result<u8> {
-
-    if pos >= 512 {
-      return Err("End of Buffer".into())
-    }
-    Ok(self.buf[pos])
-  }
+  // Remember where a name (or name suffix) was written
+  fn save_label(&mut self, labels: &[Vec<u8>], pos: usize);
 
+  // Read a single byte and move the position a single step forward
+  fn read_single_byte(&mut self) -> Result<u8> {
+    let res = self.get_single_byte(self.current_positon_in_buffer())?;
+    self.move_buffer(1)?;
 
-  // Get range of byte
-  pub fn get_range(&mut self, start: usize, length: usize) -> Result<&[u8]> {
-
-    if start + length >= 512 {
-      return Err("End of Buffer".into());
-    }
-    Ok(&self.buf[start..start + length as usize])
+    Ok(res)
   }
 
   // Read two byte and moves two steps forward
-  pub fn read_two_bytes(&mut self) -> Result<u16> {
+  fn read_two_bytes(&mut self) -> Result<u16> {
     let res = ((self.read_single_byte()? as u16) << 8) | (self.read_single_byte()? as u16);
 
     Ok(res)
   }
 
   // Read four bytes and moves four steps forward
-  pub fn read_four_bytes(&mut self) -> Result<u32> {
+  fn read_four_bytes(&mut self) -> Result<u32> {
       let res = u32::from(self.read_single_byte()?) << 24
         | u32::from(self.read_single_byte()?) << 16
         | u32::from(self.read_single_byte()?) << 8
-        | u32::from(self.read_single_byte()?) << 8
         | u32::from(self.read_single_byte()?);
 
       Ok(res)
   }
-  
 
-  pub fn read_qname(&mut self, outstr: &mut String) -> Result<()> {
-    let mut pos = self.pos;
+  // Read `len` raw bytes and move past them
+  fn read_bytes(&mut self, len: usize) -> Result<Vec<u8>> {
+    let bytes = self.get_range(self.current_positon_in_buffer(), len)?.to_vec();
+    self.move_buffer(len)?;
+
+    Ok(bytes)
+  }
+
+  // Read a possibly compressed name, keeping its labels exactly as they were sent
+  fn read_qname(&mut self, name: &mut Name) -> Result<()> {
+    let start = self.current_positon_in_buffer();
+    let mut pos = start;
+    // Octets of the name in wire format, the final root label included
+    let mut name_length = 1;
+    let mut labels = Vec::new();
 
 
     let mut jumped = false;
@@ -100,11 +91,10 @@ impl BytePacketBuffer {
     let mut jumps = 0;
 
 
-    let mut delim = "";
     loop {
 
       if jumps > max_jumps {
-        return Err(format!("Limit of jumps has been exceeded {}", max_jumps).into())
+        return Err(ProtocolError::PointerLoop { offset: pos })
       }
 
       let len = self.get_single_byte(pos)?;
@@ -124,6 +114,11 @@ impl BytePacketBuffer {
         continue;
       }
       
+      // 0x40 and 0x80 are label types that were never put to use
+      else if (len & 0xC0) != 0 {
+        return Err(ProtocolError::BadLabelType { offset: pos, byte: len });
+      }
+
       else {
         pos += 1;
 
@@ -131,12 +126,12 @@ impl BytePacketBuffer {
           break;
         }
 
-        outstr.push_str(delim);
-
-        let str_buffer = self.get_range(pos, len as usize)?;
-        outstr.push_str(&String::from_utf8_lossy(str_buffer).to_lowercase());
+        name_length += len as usize + 1;
+        if name_length > MAX_NAME_LENGTH {
+          return Err(ProtocolError::NameTooLong { offset: start });
+        }
 
-        delim = ".";
+        labels.push(self.get_range(pos, len as usize)?.to_vec());
 
         pos += len as usize;
       }
@@ -145,35 +140,26 @@ impl BytePacketBuffer {
     if !jumped {
       self.change_buffer_position(pos)?;
     }
+
+    *name = Name::from_labels(labels, start)?;
       Ok(())
   }
 
-  // Write a single byte to the buffer and advance the position
-  pub fn write(&mut self, val: u8) -> Result<()> {
-    if self.pos >= 512 {
-      return Err("End of buffer".into());
-    }
-
-    self.buf[self.pos] = val;
-    self.pos += 1;
-    Ok(())
-  }
-  
   // write a u8 to the buffer
-  pub fn write_u8(&mut self, val: u8) -> Result<()> {
+  fn write_u8(&mut self, val: u8) -> Result<()> {
     self.write(val)?;
 
     Ok(())
   }
 
-  pub fn write_u16(&mut self, val: u16) -> Result<()> {
+  fn write_u16(&mut self, val: u16) -> Result<()> {
     self.write(((val >> 8) & 0xFF) as u8)?;
     self.write((val & 0xff) as u8)?;
 
     Ok(())
   }
 
-  pub fn write_u32(&mut self, val: u32) -> Result<()> {
+  fn write_u32(&mut self, val: u32) -> Result<()> {
     self.write(((val >> 24) & 0xFF) as u8 )?;
     self.write(((val >> 16) & 0xFF) as u8)?;
     self.write(((val >> 8) & 0xFF) as u8)?;
@@ -182,32 +168,445 @@ impl BytePacketBuffer {
     Ok(())
   }
 
-  pub fn write_qname(&mut self, qname: &str) -> Result<()> {
-    for part in qname.split(".") {
-      if part.len() > 63 {
-        return Err("Label to long".into());
-      }
-      self.write(part.len() as u8)?;
-      for c in part.chars() {
-        self.write(c as u8)?;
-      }
+  // Write a name, pointing back at an earlier occurrence of its longest known suffix (RFC 1035 section 4.1.4)
+  fn write_qname(&mut self, qname: &Name) -> Result<()> {
+    write_name(self, qname, true)
+  }
+
+  // Write a name in full, for rdata where compression is not allowed (e.g. SRV targets)
+  fn write_qname_uncompressed(&mut self, qname: &Name) -> Result<()> {
+    write_name(self, qname, false)
+  }
+
+  fn set_u16(&mut self, pos: usize, val: u16) -> Result<()> {
+    self.set(pos, (val >> 8) as u8)?;
+    self.set(pos + 1, (val & 0xFF) as u8)?;
+
+    Ok(())
+
+  }
+
+  // Fill in the two byte length written at `pos` with the number of bytes written after it
+  fn set_length(&mut self, pos: usize) -> Result<()> {
+    let length = self.current_positon_in_buffer() - (pos + 2);
+    let length = u16::try_from(length).map_err(|_| ProtocolError::RdataTooLong { offset: pos, length })?;
+
+    self.set_u16(pos, length)
+  }
+}
+
+
+
+// Only suffixes with exactly the same bytes are pointed to, so the case of every name is kept
+fn write_name<T: PacketBuffer + ?Sized>(buffer: &mut T, qname: &Name, compress: bool) -> Result<()> {
+  let labels = qname.labels();
+
+  for (i, label) in labels.iter().enumerate() {
+    if label.len() > MAX_LABEL_LENGTH {
+      return Err(ProtocolError::LabelTooLong { offset: buffer.current_positon_in_buffer(), length: label.len() });
+    }
+
+    let suffix = &labels[i..];
+
+    if let Some(pos) = buffer.find_label(suffix).filter(|_| compress) {
+      buffer.write_u16(0xC000 | pos as u16)?;
+      return Ok(());
+    }
+
+    let pos = buffer.current_positon_in_buffer();
+    if pos <= MAX_POINTER_OFFSET {
+      buffer.save_label(suffix, pos);
+    }
+
+    buffer.write(label.len() as u8)?;
+    for byte in label {
+      buffer.write(*byte)?;
+    }
+  }
+  buffer.write(0)?;
+  Ok(())
+}
+
+
+
+// A view of a buffer that ends where the rdata of a record does, so a read that crosses RDLENGTH
+// fails right away instead of running on into the next record. Names in the rdata can still point
+// back at anything earlier in the message.
+pub struct RdataBuffer<'a, T: PacketBuffer + ?Sized> {
+  inner: &'a mut T,
+  start: usize,
+  length: u16,
+}
+
+impl<'a, T: PacketBuffer + ?Sized> RdataBuffer<'a, T> {
+  // The rdata starts at the current position of `inner` and is `length` bytes long
+  pub fn new(inner: &'a mut T, length: u16) -> Self {
+    let start = inner.current_positon_in_buffer();
+    Self { inner, start, length }
+  }
+
+  pub fn end(&self) -> usize {
+    self.start + self.length as usize
+  }
+
+  // Bytes of the rdata not read yet
+  pub fn remaining(&self) -> usize {
+    self.end().saturating_sub(self.inner.current_positon_in_buffer())
+  }
+
+  fn overrun(&self) -> ProtocolError {
+    ProtocolError::BadRdataLength { offset: self.start, length: self.length }
+  }
+}
+
+impl<T: PacketBuffer + ?Sized> PacketBuffer for RdataBuffer<'_, T> {
+  fn current_positon_in_buffer(&self) -> usize {
+    self.inner.current_positon_in_buffer()
+  }
+
+  fn move_buffer(&mut self, moves: usize) -> Result<()> {
+    if self.current_positon_in_buffer() + moves > self.end() {
+      return Err(self.overrun());
+    }
+    self.inner.move_buffer(moves)
+  }
+
+  fn change_buffer_position(&mut self, pos: usize) -> Result<()> {
+    if pos > self.end() {
+      return Err(self.overrun());
+    }
+    self.inner.change_buffer_position(pos)
+  }
+
+  fn get_single_byte(&mut self, pos: usize) -> Result<u8> {
+    if pos >= self.end() {
+      return Err(self.overrun());
+    }
+    self.inner.get_single_byte(pos)
+  }
+
+  fn get_range(&mut self, start: usize, length: usize) -> Result<&[u8]> {
+    if start + length > self.end() {
+      return Err(self.overrun());
+    }
+    self.inner.get_range(start, length)
+  }
+
+  fn write(&mut self, val: u8) -> Result<()> {
+    self.inner.write(val)
+  }
+
+  fn set(&mut self, pos: usize, val: u8) -> Result<()> {
+    self.inner.set(pos, val)
+  }
+
+  fn find_label(&self, labels: &[Vec<u8>]) -> Option<usize> {
+    self.inner.find_label(labels)
+  }
+
+  fn save_label(&mut self, labels: &[Vec<u8>], pos: usize) {
+    self.inner.save_label(labels, pos)
+  }
+}
+
+
+
+// A buffer with a fixed size, used for single datagrams and length prefixed TCP messages
+pub struct BytePacketBuffer {
+  pub buf: Vec<u8>,
+  pub pos: usize,
+  labels: HashMap<Vec<Vec<u8>>, usize>,
+}
+
+
+
+impl BytePacketBuffer {
+  
+  // This give a fresh buffer for holding the packet contents and field for tracking where things are
+  pub fn new () -> Self {
+    Self::with_size(MAX_UDP_PACKET_SIZE)
+  } 
+
+  // A fresh buffer able to hold a packet of `size` bytes, e.g. an EDNS payload or a TCP message
+  pub fn with_size(size: usize) -> Self {
+    Self { buf: vec![0; size], pos: 0, labels: HashMap::new() }
+  }
+
+  // The bytes written so far
+  pub fn filled(&self) -> &[u8] {
+    &self.buf[0..self.pos]
+  }
+}
+
+impl PacketBuffer for BytePacketBuffer {
+  fn current_positon_in_buffer(&self) -> usize {
+    self.pos
+  }
+
+  fn move_buffer(&mut self, moves: usize) -> Result<()> {
+    self.pos += moves;
+
+    Ok(())
+  }
+
+  fn change_buffer_position(&mut self, pos: usize) -> Result<()> {
+    self.pos = pos;
+
+    Ok(())
+  }
+
+  fn get_single_byte(&mut self, pos: usize) -> Result<u8> {
+
+    if pos >= self.buf.len() {
+      return Err(ProtocolError::Truncated { offset: pos })
     }
-    self.write(0)?;
+    Ok(self.buf[pos])
+  }
+
+
+  fn get_range(&mut self, start: usize, length: usize) -> Result<&[u8]> {
+
+    if start + length > self.buf.len() {
+      return Err(ProtocolError::Truncated { offset: start });
+    }
+    Ok(&self.buf[start..start + length])
+  }
+
+  fn write(&mut self, val: u8) -> Result<()> {
+    if self.pos >= self.buf.len() {
+      return Err(ProtocolError::BufferFull { offset: self.pos });
+    }
+
+    self.buf[self.pos] = val;
+    self.pos += 1;
     Ok(())
   }
 
-  pub fn set(&mut self, pos: usize, val: u8) -> Result<()>{
+  fn set(&mut self, pos: usize, val: u8) -> Result<()>{
+    if pos >= self.buf.len() {
+      return Err(ProtocolError::BufferFull { offset: pos });
+    }
+
     self.buf[pos] = val;
 
     Ok(())
   }
 
-  pub fn set_u16(&mut self, pos: usize, val: u16) -> Result<()> {
-    self.set(pos, (val >> 8) as u8)?;
-    self.set(pos + 1, (val & 0xFF) as u8)?;
+  fn find_label(&self, labels: &[Vec<u8>]) -> Option<usize> {
+    self.labels.get(labels).copied()
+  }
+
+  fn save_label(&mut self, labels: &[Vec<u8>], pos: usize) {
+    self.labels.entry(labels.to_vec()).or_insert(pos);
+  }
+}
+
+
+impl Default for BytePacketBuffer {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+
+
+// A buffer that grows as it is written to, for messages with no upper bound such as zone transfer streams
+pub struct VectorPacketBuffer {
+  pub buf: Vec<u8>,
+  pub pos: usize,
+  labels: HashMap<Vec<Vec<u8>>, usize>,
+}
+
+impl VectorPacketBuffer {
+  pub fn new() -> Self {
+    Self { buf: Vec::new(), pos: 0, labels: HashMap::new() }
+  }
+
+  // Wrap already received bytes so they can be parsed
+  pub fn from_bytes(buf: Vec<u8>) -> Self {
+    Self { buf, pos: 0, labels: HashMap::new() }
+  }
+}
+
+impl PacketBuffer for VectorPacketBuffer {
+  fn current_positon_in_buffer(&self) -> usize {
+    self.pos
+  }
+
+  fn move_buffer(&mut self, moves: usize) -> Result<()> {
+    self.pos += moves;
+
+    Ok(())
+  }
+
+  fn change_buffer_position(&mut self, pos: usize) -> Result<()> {
+    self.pos = pos;
+
+    Ok(())
+  }
+
+  fn get_single_byte(&mut self, pos: usize) -> Result<u8> {
+    match self.buf.get(pos) {
+      Some(byte) => Ok(*byte),
+      None => Err(ProtocolError::Truncated { offset: pos }),
+    }
+  }
+
+  fn get_range(&mut self, start: usize, length: usize) -> Result<&[u8]> {
+    if start + length > self.buf.len() {
+      return Err(ProtocolError::Truncated { offset: start });
+    }
+    Ok(&self.buf[start..start + length])
+  }
+
+  fn write(&mut self, val: u8) -> Result<()> {
+    // Writing after a seek past the end pads the gap with zeroes
+    if self.pos >= self.buf.len() {
+      self.buf.resize(self.pos, 0);
+      self.buf.push(val);
+    } else {
+      self.buf[self.pos] = val;
+    }
+    self.pos += 1;
 
     Ok(())
+  }
+
+  fn set(&mut self, pos: usize, val: u8) -> Result<()> {
+    match self.buf.get_mut(pos) {
+      Some(byte) => {
+        *byte = val;
+        Ok(())
+      }
+      None => Err(ProtocolError::BufferFull { offset: pos }),
+    }
+  }
+
+  fn find_label(&self, labels: &[Vec<u8>]) -> Option<usize> {
+    self.labels.get(labels).copied()
+  }
+
+  fn save_label(&mut self, labels: &[Vec<u8>], pos: usize) {
+    self.labels.entry(labels.to_vec()).or_insert(pos);
+  }
+}
 
+
+impl Default for VectorPacketBuffer {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util::name;
+
+  fn read_name_at(buffer: &mut VectorPacketBuffer, pos: usize) -> Result<Name> {
+    buffer.change_buffer_position(pos)?;
+    let mut name = Name::root();
+    buffer.read_qname(&mut name)?;
+    Ok(name)
+  }
+
+  #[test]
+  fn repeated_suffixes_are_pointed_to() {
+    let mut buffer = VectorPacketBuffer::new();
+    let names = [name("www.example.com"), name("mail.example.com"), name("example.com"), name("www.example.com")];
+
+    let mut starts = Vec::new();
+    for name in &names {
+      starts.push(buffer.pos);
+      buffer.write_qname(name).unwrap();
+    }
+
+    // In full, then `mail` and a pointer, then a pointer alone, twice
+    assert_eq!(starts, vec![0, 17, 24, 26]);
+    assert_eq!(buffer.buf.len(), 28);
+
+    for (name, start) in names.iter().zip(starts) {
+      assert_eq!(&read_name_at(&mut buffer, start).unwrap(), name);
+    }
+  }
+
+  #[test]
+  fn suffixes_differing_in_case_are_not_shared() {
+    let mut buffer = VectorPacketBuffer::new();
+    buffer.write_qname(&name("www.example.com")).unwrap();
+    buffer.write_qname(&name("WWW.Example.COM")).unwrap();
+    assert_eq!(buffer.buf.len(), 34);
+
+    let second = read_name_at(&mut buffer, 17).unwrap();
+    assert_eq!(second.labels(), &[b"WWW".to_vec(), b"Example".to_vec(), b"COM".to_vec()]);
   }
 
+  #[test]
+  fn uncompressed_names_are_written_in_full() {
+    let mut buffer = VectorPacketBuffer::new();
+    buffer.write_qname(&name("example.com")).unwrap();
+    buffer.write_qname_uncompressed(&name("example.com")).unwrap();
+    assert_eq!(buffer.buf.len(), 26);
+  }
+
+  #[test]
+  fn pointers_reach_the_last_addressable_offset() {
+    let mut buffer = VectorPacketBuffer::new();
+    buffer.change_buffer_position(MAX_POINTER_OFFSET).unwrap();
+    buffer.write_qname(&name("example.com")).unwrap();
+
+    let pointer_at = buffer.pos;
+    buffer.write_qname(&name("example.com")).unwrap();
+    assert_eq!(&buffer.buf[pointer_at..], &[0xFF, 0xFF]);
+    assert_eq!(read_name_at(&mut buffer, pointer_at).unwrap(), name("example.com"));
+
+    // Past 0x3FFF a name can not be pointed to, so it is written in full again
+    let beyond = buffer.pos;
+    buffer.write_qname(&name("example.org")).unwrap();
+    let again = buffer.pos;
+    buffer.write_qname(&name("example.org")).unwrap();
+    assert_eq!(again - beyond, buffer.pos - again);
+    assert_eq!(read_name_at(&mut buffer, again).unwrap(), name("example.org"));
+  }
+
+  #[test]
+  fn reserved_label_types_are_rejected() {
+    for byte in [0x41, 0x80] {
+      let mut buffer = VectorPacketBuffer::from_bytes(vec![0x03, b'c', b'o', b'm', byte, 0x00]);
+      assert_eq!(read_name_at(&mut buffer, 0), Err(ProtocolError::BadLabelType { offset: 4, byte }));
+    }
+  }
+
+  #[test]
+  fn pointer_to_itself_is_rejected() {
+    let mut buffer = VectorPacketBuffer::from_bytes(vec![0xC0, 0x00]);
+    assert!(matches!(read_name_at(&mut buffer, 0), Err(ProtocolError::PointerLoop { .. })));
+  }
+
+  #[test]
+  fn pointer_chains_are_capped() {
+    // A chain of `jumps` pointers, each to the next, ending in `com`
+    let chain = |jumps: usize| {
+      let mut bytes = Vec::new();
+      for i in 0..jumps {
+        bytes.extend_from_slice(&[0xC0, (2 * (i + 1)) as u8]);
+      }
+      bytes.extend_from_slice(b"\x03com\x00");
+      VectorPacketBuffer::from_bytes(bytes)
+    };
+
+    assert_eq!(read_name_at(&mut chain(5), 0).unwrap(), name("com"));
+    assert!(matches!(read_name_at(&mut chain(6), 0), Err(ProtocolError::PointerLoop { .. })));
+  }
+
+  #[test]
+  fn reading_a_pointer_leaves_the_position_after_it() {
+    let mut buffer = VectorPacketBuffer::new();
+    buffer.write_qname(&name("example.com")).unwrap();
+    buffer.write_qname(&name("www.example.com")).unwrap();
+    buffer.write_u16(0xABCD).unwrap();
+
+    read_name_at(&mut buffer, 13).unwrap();
+    assert_eq!(buffer.read_two_bytes().unwrap(), 0xABCD);
+  }
 }
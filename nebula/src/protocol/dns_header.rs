@@ -1,4 +1,4 @@
-use super::{byte_packet::BytePacketBuffer, res_code::ResultCode};
+use super::{byte_packet::PacketBuffer, error::ProtocolError, opcode::Opcode, res_code::ResultCode};
  
 
 #[derive(Debug, Clone)]
@@ -8,7 +8,7 @@ pub struct DnsHeader {
   pub recursion_desired: bool,
   pub truncated_message: bool,
   pub authoritative_answer: bool,
-  pub opcode: u8,
+  pub opcode: Opcode,
   pub response: bool,
 
   pub rescode: ResultCode,
@@ -30,7 +30,7 @@ impl DnsHeader {
       recursion_desired: false,
       truncated_message: false,
       authoritative_answer: false,
-      opcode: 0,
+      opcode: Opcode::QUERY,
       response: false,
       rescode: ResultCode::NOERROR,
       checking_disabled: false, 
@@ -44,7 +44,7 @@ impl DnsHeader {
     }
   }
 
-  pub fn read(&mut self, buffer: &mut BytePacketBuffer) -> Result<(), Box<dyn std::error::Error>> {
+  pub fn read<T: PacketBuffer>(&mut self, buffer: &mut T) -> Result<(), ProtocolError> {
     self.id = buffer.read_two_bytes()?;
 
     let flags = buffer.read_two_bytes()?;
@@ -54,10 +54,12 @@ impl DnsHeader {
     self.recursion_desired = (a &(1 << 0)) > 0;
     self.truncated_message = (a & (1 << 1)) > 0;
     self.authoritative_answer = (a & (1 << 2)) > 0;
-    self.opcode = (a >> 3) & 0x0FF;
+    // The opcode is the four bits between QR and AA
+    self.opcode = Opcode::from_num((a >> 3) & 0x0F);
     self.response = (a & (1 << 7 )) > 0;
 
-    self.rescode = ResultCode::from_num(b & 0x0f);
+    // Only the lower four bits, the packet adds the rest from the OPT record
+    self.rescode = ResultCode::from_num((b & 0x0f) as u16);
     self.checking_disabled = (b & (1 << 4)) > 0;
     self.authed_data = (b & (1 << 5)) > 0;
     self.z = (b & (1 << 6)) > 0;
@@ -73,19 +75,19 @@ impl DnsHeader {
       Ok(())
 }
 
-pub fn write(&self, buffer: &mut BytePacketBuffer) -> Result<(), Box<dyn std::error::Error>> {
+pub fn write<T: PacketBuffer>(&self, buffer: &mut T) -> Result<(), ProtocolError> {
   buffer.write_u16(self.id)?;
 
   buffer.write_u8(
     (self.recursion_desired as u8)
     | ((self.truncated_message  as u8) << 1)
     | ((self.authoritative_answer as u8) << 2)
-    | ((self.opcode << 3))
-    | ((self.response as u8) << 7) as u8,
+    | ((self.opcode.to_num() & 0x0F) << 3)
+    | ((self.response as u8) << 7),
   )?;
 
   buffer.write_u8(
-    (self.rescode as u8)
+    ((self.rescode.to_num() & 0x0F) as u8)
     | ((self.checking_disabled as u8) << 4)
     | ((self.authed_data as u8) << 5)
     | ((self.z as u8) << 6)
@@ -101,4 +103,10 @@ pub fn write(&self, buffer: &mut BytePacketBuffer) -> Result<(), Box<dyn std::er
   Ok(())
 }
     
-}
\ No newline at end of file
+}
+
+impl Default for DnsHeader {
+  fn default() -> Self {
+    Self::new()
+  }
+}
@@ -1,7 +1,9 @@
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
 
-use super::{byte_packet::BytePacketBuffer, dns_header::DnsHeader, dns_question::DnsQuestion, dns_record::DnsRecord};
+use super::{query_type::QueryType, byte_packet::{PacketBuffer, VectorPacketBuffer, MAX_EDNS_PACKET_SIZE, MAX_UDP_PACKET_SIZE}, dns_header::DnsHeader, dns_question::DnsQuestion, dns_record::DnsRecord, ede::ExtendedError, edns::Edns, error::ProtocolError, name::Name, res_code::ResultCode};
 
+// Longest CNAME chain followed, in one response or across several
+pub const MAX_CNAME_CHAIN: usize = 8;
 
 #[derive(Debug, Clone)]
 pub struct DnsPacket {
@@ -9,8 +11,8 @@ pub struct DnsPacket {
   pub questions: Vec<DnsQuestion>,
   pub answers: Vec<DnsRecord>,
   pub authorities: Vec<DnsRecord>,
-  pub resources: Vec<DnsRecord>
-
+  pub resources: Vec<DnsRecord>,
+  pub edns: Option<Edns>,
 }
 
 impl DnsPacket {
@@ -20,16 +22,17 @@ impl DnsPacket {
       questions: Vec::new(), 
       answers: Vec::new(), 
       authorities: Vec::new(), 
-      resources: Vec::new() 
+      resources: Vec::new(),
+      edns: None,
     }
   }
 
-  pub fn from_buffer(buffer: &mut BytePacketBuffer) -> Result<DnsPacket, Box<dyn std::error::Error>> {
+  pub fn from_buffer<T: PacketBuffer>(buffer: &mut T) -> Result<DnsPacket, ProtocolError> {
     let mut result = DnsPacket::new();
     result.header.read(buffer)?;
 
     for _ in 0..result.header.questions {
-      let mut question = DnsQuestion::new("".to_string(), super::query_type::QueryType::UNKNOWN(0));
+      let mut question = DnsQuestion::new(Name::root(), QueryType::UNKNOWN(0));
       question.read(buffer)?;
       result.questions.push(question);
     }
@@ -46,17 +49,45 @@ impl DnsPacket {
     }
 
     for _ in 0..result.header.resource_entries {
-        let rec = DnsRecord::read(buffer)?;
-        result.resources.push(rec);
+        // The OPT pseudo-record lives in the additional section but is not a real resource
+        match DnsRecord::read(buffer)? {
+          DnsRecord::OPT { edns } => result.edns = Some(edns),
+          rec => result.resources.push(rec),
+        }
+    }
+
+    // The OPT record holds the upper eight bits of a 12 bit RCODE (RFC 6891 section 6.1.3)
+    if let Some(edns) = &result.edns {
+      let rcode = ((edns.extended_rcode as u16) << 4) | result.header.rescode.to_num();
+      result.header.rescode = ResultCode::from_num(rcode);
     }
+
     Ok(result)
   }
 
-  pub fn write(&mut self, buffer: &mut BytePacketBuffer) -> Result<(), Box<dyn std::error::Error>> {
+  pub fn write<T: PacketBuffer>(&mut self, buffer: &mut T) -> Result<(), ProtocolError> {
+    self.write_with_offsets(buffer)?;
+
+    Ok(())
+  }
+
+  // Write the packet, returning where the questions end followed by where every record of the
+  // answer, authority and additional sections ends. The OPT record fills the rest.
+  fn write_with_offsets<T: PacketBuffer>(&mut self, buffer: &mut T) -> Result<Vec<usize>, ProtocolError> {
     self.header.questions = self.questions.len() as u16;
     self.header.answers = self.answers.len() as u16;
     self.header.authoritative_entries = self.authorities.len() as u16;
-    self.header.resource_entries = self.resources.len() as u16;
+    // RCODEs past the header's four bits can only be sent along with an OPT record, and one is
+    // never made up here as a client that did not send one must not get one (RFC 6891 section 7)
+    let rcode = self.header.rescode.to_num();
+    if rcode > 0x0F && self.edns.is_none() {
+      return Err(ProtocolError::ExtendedRcodeWithoutEdns { rcode });
+    }
+    if let Some(edns) = &mut self.edns {
+      edns.extended_rcode = (rcode >> 4) as u8;
+    }
+
+    self.header.resource_entries = (self.resources.len() + self.edns.is_some() as usize) as u16;
 
     self.header.write(buffer)?;
 
@@ -64,58 +95,329 @@ impl DnsPacket {
       question.write(buffer)?;
     }
 
-    for rec in &self.answers {
+    let mut ends = vec![buffer.current_positon_in_buffer()];
+    for rec in self.answers.iter().chain(&self.authorities).chain(&self.resources) {
       rec.write(buffer)?;
+      ends.push(buffer.current_positon_in_buffer());
     }
 
-    for rec in &self.authorities {
-      rec.write(buffer)?;
+    if let Some(edns) = &self.edns {
+      edns.write(buffer)?;
     }
 
-    for rec in &self.resources {
-      rec.write(buffer)?;
+    Ok(ends)
+  }
+
+  // Write the packet in at most `max_size` bytes. When it does not fit, whole records are dropped
+  // from the end of the additional, authority and answer sections, in that order (RFC 2181 section 9).
+  // Losing additional data is harmless, anything else sets the TC bit so the client retries over TCP.
+  // The packet is serialized once and cut at the last record boundary that leaves room for the
+  // OPT record. Names only ever point back, so the records kept never refer to the ones cut.
+  pub fn write_truncated<T: PacketBuffer>(&mut self, buffer: &mut T, max_size: usize) -> Result<(), ProtocolError> {
+    let mut full = VectorPacketBuffer::new();
+    let ends = self.write_with_offsets(&mut full)?;
+
+    let opt_start = ends[ends.len() - 1];
+    let opt_length = full.buf.len() - opt_start;
+    let kept = (0..ends.len())
+      .rev()
+      .find(|&count| ends[count] + opt_length <= max_size)
+      .ok_or(ProtocolError::ResponseTooLarge { max_size })?;
+
+    let answers = kept.min(self.answers.len());
+    let authorities = (kept - answers).min(self.authorities.len());
+    let resources = kept - answers - authorities;
+
+    if answers < self.answers.len() || authorities < self.authorities.len() {
+      self.header.truncated_message = true;
+    }
+    self.answers.truncate(answers);
+    self.authorities.truncate(authorities);
+    self.resources.truncate(resources);
+    self.header.answers = answers as u16;
+    self.header.authoritative_entries = authorities as u16;
+    self.header.resource_entries = (resources + self.edns.is_some() as usize) as u16;
+
+    // The header goes in again with the counts and TC bit of what is left
+    let mut header = VectorPacketBuffer::new();
+    self.header.write(&mut header)?;
+
+    let kept_bytes = &full.buf[header.buf.len()..ends[kept]];
+    let opt_bytes = &full.buf[opt_start..];
+    for byte in header.buf.iter().chain(kept_bytes).chain(opt_bytes) {
+      buffer.write_u8(*byte)?;
     }
 
     Ok(())
   }
 
-  pub fn get_random_a(&self) -> Option<Ipv4Addr> {
+  // Explain a failure with an Extended DNS Error, dropped for clients that do not speak EDNS
+  pub fn add_extended_error(&mut self, error: ExtendedError) {
+    if let Some(edns) = &mut self.edns {
+      edns.add_extended_error(error);
+    }
+  }
+
+  // The largest UDP response the sender of this packet is able to receive
+  pub fn max_udp_size(&self) -> usize {
+    match &self.edns {
+      Some(edns) => (edns.udp_payload_size as usize).clamp(MAX_UDP_PACKET_SIZE, MAX_EDNS_PACKET_SIZE),
+      None => MAX_UDP_PACKET_SIZE,
+    }
+  }
+
+  // Every IPv4 and IPv6 address in the answer section
+  pub fn get_addrs(&self) -> Vec<IpAddr> {
     self.answers
       .iter()
       .filter_map(|record| match record {
-          DnsRecord::A { addr, .. } => Some(*addr), 
-            _=> None
-          })
-          .next()
+        DnsRecord::A { addr, .. } => Some(IpAddr::V4(*addr)),
+        DnsRecord::AAAA { addr, .. } => Some(IpAddr::V6(*addr)),
+        _ => None,
+      })
+      .collect()
+  }
+
+  // Follow the CNAMEs of the answer section from `qname`, giving the name the chain ends at along
+  // with the CNAME records on the way
+  pub fn get_cname_chain<'a>(&'a self, qname: &Name) -> (Name, Vec<&'a DnsRecord>) {
+    let mut target = qname.clone();
+    let mut chain = Vec::new();
+
+    while chain.len() < MAX_CNAME_CHAIN {
+      let cname = self.answers.iter().find(|rec| rec.query_type() == QueryType::CNAME && *rec.domain() == target);
+      let Some(rec @ DnsRecord::CNAME { host, .. }) = cname else {
+        break;
+      };
+
+      target = host.clone();
+      chain.push(rec);
+    }
+
+    (target, chain)
   }
 
-  fn get_ns<'a>(&'a self, qname: &'a str) -> impl Iterator<Item = (&'a str, &'a str)> {
+  // NS records of the authority section for zones that contain `qname`
+  fn get_ns<'a>(&'a self, qname: &'a Name) -> impl Iterator<Item = (&'a Name, &'a Name)> {
     self.authorities
         .iter()
         .filter_map(|record| match record {
-          DnsRecord::NS { domain, host, .. } => Some((domain.as_str(), host.as_str())),
+          DnsRecord::NS { domain, host, .. } => Some((domain, host)),
           _=> None
         })
-        .filter(move |(domain, _)| qname.ends_with(*domain))
+        .filter(move |(domain, _)| qname.is_subdomain_of(domain))
   }
 
-  pub fn get_resolved_ns(&self, qname: &str) -> Option<Ipv4Addr> {
+  // The deepest zone the authority section delegates `qname` to, the zone cut of a referral
+  pub fn get_zone_cut<'a>(&'a self, qname: &'a Name) -> Option<&'a Name> {
     self.get_ns(qname)
-    .flat_map(|(_, host) | {
-      self.resources
-      .iter()
-      .filter_map(move | record| match record {
-        DnsRecord::A { domain, addr, .. } if domain == host => Some(addr),
-        _=> None,
-      })
-    })
-    .map(|addr| *addr)
-    .next()
+        .map(|(domain, _)| domain)
+        .max_by_key(|domain| domain.labels().len())
   }
-  pub fn get_unresolved_ns<'a>(&'a self, qname: &'a str) -> Option<&'a str> {
-    self.get_ns(qname)
-    .map(|(_, host)| host)
-    .next()
+
+  // Drop every record the servers of `zone` have no say over (RFC 2181 section 5.4.1), the
+  // classic way of poisoning a cache with glue for someone else's names. Every record has to be
+  // inside `zone`, authority records also have to lie between `zone` and `qname`, and additional
+  // records are only kept as glue for the name servers that remain.
+  pub fn remove_out_of_bailiwick(&mut self, qname: &Name, zone: &Name) {
+    self.answers.retain(|rec| rec.domain().is_subdomain_of(zone));
+    self.authorities.retain(|rec| rec.domain().is_subdomain_of(zone) && qname.is_subdomain_of(rec.domain()));
+
+    let hosts: Vec<Name> = self.get_ns(qname).map(|(_, host)| host.clone()).collect();
+    self.resources.retain(|rec| rec.domain().is_subdomain_of(zone) && hosts.contains(rec.domain()));
+  }
+
+  // Every address, IPv4 and IPv6, the additional section gives for a name server of `qname`
+  pub fn get_resolved_ns(&self, qname: &Name) -> Vec<IpAddr> {
+    let mut addrs = Vec::new();
+
+    for (_, host) in self.get_ns(qname) {
+      for record in &self.resources {
+        let addr = match record {
+          DnsRecord::A { domain, addr, .. } if domain == host => IpAddr::V4(*addr),
+          DnsRecord::AAAA { domain, addr, .. } if domain == host => IpAddr::V6(*addr),
+          _ => continue,
+        };
+
+        if !addrs.contains(&addr) {
+          addrs.push(addr);
+        }
+      }
+    }
+
+    addrs
+  }
+
+  // The names of every name server of `qname`, for when no glue came with them
+  pub fn get_unresolved_ns<'a>(&'a self, qname: &'a Name) -> Vec<&'a Name> {
+    let mut hosts: Vec<&Name> = Vec::new();
+
+    for (_, host) in self.get_ns(qname) {
+      if !hosts.contains(&host) {
+        hosts.push(host);
+      }
+    }
+
+    hosts
+  }
+
+}
+
+impl Default for DnsPacket {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{protocol::byte_packet::BytePacketBuffer, test_util::{a_record, cname_record, name, ns_record}};
+
+  fn response_with(answers: usize, resources: usize) -> DnsPacket {
+    let mut packet = DnsPacket::new();
+    packet.header.response = true;
+    packet.questions.push(DnsQuestion::new(name("www.example.com"), QueryType::A));
+    packet.answers = (0..answers).map(|i| a_record("www.example.com", i as u8)).collect();
+    packet.resources = (0..resources).map(|i| a_record("ns.example.com", i as u8)).collect();
+    packet.edns = Some(Edns::new());
+    packet
+  }
+
+  #[test]
+  fn oversized_answer_is_truncated_with_tc_set() {
+    let mut packet = response_with(100, 0);
+
+    let mut buffer = BytePacketBuffer::with_size(MAX_UDP_PACKET_SIZE);
+    packet.write_truncated(&mut buffer, MAX_UDP_PACKET_SIZE).unwrap();
+    assert!(buffer.pos <= MAX_UDP_PACKET_SIZE);
+
+    buffer.buf.truncate(buffer.pos);
+    buffer.pos = 0;
+    let parsed = DnsPacket::from_buffer(&mut buffer).unwrap();
+    assert!(parsed.header.truncated_message);
+    assert!(!parsed.answers.is_empty() && parsed.answers.len() < 100);
+    assert_eq!(parsed.answers, packet.answers);
+    assert!(parsed.edns.is_some());
+  }
+
+  #[test]
+  fn dropping_additional_records_does_not_set_tc() {
+    let mut packet = response_with(2, 100);
+
+    let mut buffer = BytePacketBuffer::with_size(MAX_UDP_PACKET_SIZE);
+    packet.write_truncated(&mut buffer, MAX_UDP_PACKET_SIZE).unwrap();
+
+    buffer.buf.truncate(buffer.pos);
+    buffer.pos = 0;
+    let parsed = DnsPacket::from_buffer(&mut buffer).unwrap();
+    assert!(!parsed.header.truncated_message);
+    assert_eq!(parsed.answers.len(), 2);
+    assert!(parsed.resources.len() < 100);
+  }
+
+  #[test]
+  fn packet_that_fits_is_written_whole() {
+    let mut packet = response_with(3, 1);
+    let mut whole = VectorPacketBuffer::new();
+    packet.clone().write(&mut whole).unwrap();
+
+    let mut buffer = BytePacketBuffer::with_size(MAX_UDP_PACKET_SIZE);
+    packet.write_truncated(&mut buffer, MAX_UDP_PACKET_SIZE).unwrap();
+    assert_eq!(buffer.filled(), &whole.buf[..]);
+  }
+
+  #[test]
+  fn question_too_large_for_the_limit_is_an_error() {
+    let mut packet = response_with(1, 0);
+    let mut buffer = BytePacketBuffer::with_size(MAX_UDP_PACKET_SIZE);
+    assert_eq!(packet.write_truncated(&mut buffer, 20), Err(ProtocolError::ResponseTooLarge { max_size: 20 }));
   }
 
-}
\ No newline at end of file
+  #[test]
+  fn extended_rcode_needs_an_opt_record() {
+    let mut packet = response_with(0, 0);
+    packet.header.rescode = ResultCode::BADCOOKIE;
+    packet.edns = None;
+
+    let mut buffer = BytePacketBuffer::new();
+    assert_eq!(packet.write(&mut buffer), Err(ProtocolError::ExtendedRcodeWithoutEdns { rcode: 23 }));
+
+    packet.edns = Some(Edns::new());
+    let mut buffer = BytePacketBuffer::new();
+    packet.write(&mut buffer).unwrap();
+    buffer.pos = 0;
+    assert_eq!(DnsPacket::from_buffer(&mut buffer).unwrap().header.rescode, ResultCode::BADCOOKIE);
+  }
+
+  #[test]
+  fn glue_for_a_sibling_zone_is_dropped() {
+    let mut packet = DnsPacket::new();
+    packet.authorities.push(ns_record("example.com", "ns.example.com"));
+    packet.authorities.push(ns_record("example.com", "ns.example.net"));
+    packet.resources.push(a_record("ns.example.com", 1));
+    packet.resources.push(a_record("ns.example.net", 2));
+    packet.resources.push(a_record("www.example.org", 3));
+
+    packet.remove_out_of_bailiwick(&name("www.example.com"), &name("com"));
+
+    assert_eq!(packet.authorities.len(), 2);
+    assert_eq!(packet.resources, vec![a_record("ns.example.com", 1)]);
+  }
+
+  #[test]
+  fn name_servers_outside_the_zone_are_dropped() {
+    let mut packet = DnsPacket::new();
+    packet.answers.push(a_record("www.example.com", 1));
+    packet.answers.push(a_record("www.example.org", 2));
+    packet.authorities.push(ns_record("example.com", "ns.example.com"));
+    packet.authorities.push(ns_record("org", "ns.example.org"));
+    packet.authorities.push(ns_record("other.example.com", "ns.example.com"));
+    packet.resources.push(a_record("ns.example.org", 3));
+
+    packet.remove_out_of_bailiwick(&name("www.example.com"), &name("example.com"));
+
+    assert_eq!(packet.answers, vec![a_record("www.example.com", 1)]);
+    assert_eq!(packet.authorities, vec![ns_record("example.com", "ns.example.com")]);
+    assert!(packet.resources.is_empty());
+  }
+
+  #[test]
+  fn bailiwick_respects_label_boundaries() {
+    let mut packet = DnsPacket::new();
+    packet.answers.push(a_record("example.com", 1));
+    packet.authorities.push(ns_record("example.com", "ns.example.com"));
+    packet.resources.push(a_record("ns.example.com", 2));
+
+    packet.remove_out_of_bailiwick(&name("example.com"), &name("ample.com"));
+
+    assert!(packet.answers.is_empty());
+    assert!(packet.authorities.is_empty());
+    assert!(packet.resources.is_empty());
+  }
+
+  #[test]
+  fn cname_chain_is_followed_to_its_end() {
+    let mut packet = DnsPacket::new();
+    packet.answers.push(cname_record("cdn.example.net", "edge.example.org"));
+    packet.answers.push(cname_record("www.example.com", "cdn.example.net"));
+
+    let (target, chain) = packet.get_cname_chain(&name("www.example.com"));
+    assert_eq!(target, name("edge.example.org"));
+    assert_eq!(chain, vec![&packet.answers[1], &packet.answers[0]]);
+
+    let (target, chain) = packet.get_cname_chain(&name("mail.example.com"));
+    assert_eq!(target, name("mail.example.com"));
+    assert!(chain.is_empty());
+  }
+
+  #[test]
+  fn cname_loop_is_cut_off() {
+    let mut packet = DnsPacket::new();
+    packet.answers.push(cname_record("a.example", "b.example"));
+    packet.answers.push(cname_record("b.example", "a.example"));
+
+    let (_, chain) = packet.get_cname_chain(&name("a.example"));
+    assert_eq!(chain.len(), MAX_CNAME_CHAIN);
+  }
+}
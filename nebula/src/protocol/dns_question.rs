@@ -1,35 +1,65 @@
-use super::{byte_packet::BytePacketBuffer, query_type::QueryType};
+use super::{byte_packet::PacketBuffer, error::ProtocolError, name::Name, query_class::QueryClass, query_type::QueryType};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DnsQuestion {
-  pub name: String,
-  pub qtype: QueryType
+  pub name: Name,
+  pub qtype: QueryType,
+  pub qclass: QueryClass
 }
 
 impl DnsQuestion {
-  pub fn new(name: String, qtype: QueryType) -> Self {
+  pub fn new(name: Name, qtype: QueryType) -> Self {
+    Self::with_class(name, qtype, QueryClass::IN)
+  }
+
+  // A question for a name as a user would type it, Unicode names being turned into A-labels
+  pub fn from_text(name: &str, qtype: QueryType) -> Result<Self, ProtocolError> {
+    Ok(Self::new(Name::from_unicode(name)?, qtype))
+  }
+
+  pub fn with_class(name: Name, qtype: QueryType, qclass: QueryClass) -> Self {
     Self {
       name,
-      qtype
-
+      qtype,
+      qclass
     }
   }
 
-  pub fn read(&mut self, buffer: &mut BytePacketBuffer) -> Result<(), Box<dyn std::error::Error>> {
+  pub fn read<T: PacketBuffer>(&mut self, buffer: &mut T) -> Result<(), ProtocolError> {
     buffer.read_qname(&mut self.name)?;
     self.qtype = QueryType::from_num(buffer.read_two_bytes()?);
-    let _ = buffer.read_two_bytes()?;
+    self.qclass = QueryClass::from_num(buffer.read_two_bytes()?);
 
     Ok(())
   }
 
-  pub fn write(&self, buffer: &mut BytePacketBuffer) -> Result<(), Box<dyn std::error::Error>> {
+  pub fn write<T: PacketBuffer>(&self, buffer: &mut T) -> Result<(), ProtocolError> {
     buffer.write_qname(&self.name)?;
 
     let type_num = self.qtype.to_num();
-    buffer.write_u16(type_num);
-    buffer.write_u16(1);
+    buffer.write_u16(type_num)?;
+    buffer.write_u16(self.qclass.to_num())?;
 
     Ok(())
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn unicode_names_become_a_labels() {
+    let question = DnsQuestion::from_text("bücher.example", QueryType::A).unwrap();
+    assert_eq!(question.name, Name::parse("xn--bcher-kva.example").unwrap());
+    assert_eq!(question.name.to_string(), "xn--bcher-kva.example.");
+    assert_eq!(question.qclass, QueryClass::IN);
+  }
+
+  #[test]
+  fn ascii_names_are_kept() {
+    let question = DnsQuestion::from_text("www.example.com", QueryType::AAAA).unwrap();
+    assert_eq!(question.name, Name::parse("www.example.com").unwrap());
+    assert_eq!(question.qtype, QueryType::AAAA);
+  }
+}
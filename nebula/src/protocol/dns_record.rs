@@ -1,57 +1,155 @@
 use std::net::{Ipv4Addr, Ipv6Addr};
 
-use super::{byte_packet::BytePacketBuffer, query_type::QueryType};
+use super::{byte_packet::{PacketBuffer, RdataBuffer}, edns::Edns, error::ProtocolError, name::Name, query_class::QueryClass, query_type::QueryType, svcb::{read_params, write_params, SvcParam}};
+
+// The owner name of OPT, which has no name of its own
+static ROOT: Name = Name::root();
 
 #[derive(Debug, PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
 
 pub enum DnsRecord {
   UNKNOWN {
-    domain: String,
+    domain: Name,
     qtype: u16,
-    data_length: u16,
+    // The rdata exactly as received, so it can be passed on untouched (RFC 3597)
+    data: Vec<u8>,
+    class: QueryClass,
     ttl: u32
   }, //0
   A {
-    domain: String,
+    domain: Name,
     addr: Ipv4Addr,
+    class: QueryClass,
     ttl: u32
   }, //1
   NS {
-    domain: String,
-    host: String,
+    domain: Name,
+    host: Name,
+    class: QueryClass,
     ttl: u32
   },
   CNAME {
-    domain: String,
-    host: String,
+    domain: Name,
+    host: Name,
+    class: QueryClass,
+    ttl: u32
+  },
+  SOA {
+    domain: Name,
+    mname: Name,
+    rname: Name,
+    serial: u32,
+    refresh: u32,
+    retry: u32,
+    expire: u32,
+    minimum: u32,
+    class: QueryClass,
+    ttl: u32
+  },
+  PTR {
+    domain: Name,
+    host: Name,
+    class: QueryClass,
     ttl: u32
   },
   MX {
-    domain: String,
+    domain: Name,
     priority: u16,
-    host: String,
+    host: Name,
+    class: QueryClass,
+    ttl: u32
+  },
+  TXT {
+    domain: Name,
+    // Each character-string, without its length prefix
+    data: Vec<Vec<u8>>,
+    class: QueryClass,
     ttl: u32
   },
   AAAA {
-    domain: String,
+    domain: Name,
     addr: Ipv6Addr,
+    class: QueryClass,
     ttl: u32,
+  },
+  SRV {
+    domain: Name,
+    priority: u16,
+    weight: u16,
+    port: u16,
+    target: Name,
+    class: QueryClass,
+    ttl: u32
+  },
+  SSHFP {
+    domain: Name,
+    algorithm: u8,
+    fingerprint_type: u8,
+    fingerprint: Vec<u8>,
+    class: QueryClass,
+    ttl: u32
+  },
+  TLSA {
+    domain: Name,
+    usage: u8,
+    selector: u8,
+    matching_type: u8,
+    data: Vec<u8>,
+    class: QueryClass,
+    ttl: u32
+  },
+  SVCB {
+    domain: Name,
+    priority: u16,
+    target: Name,
+    params: Vec<SvcParam>,
+    class: QueryClass,
+    ttl: u32
+  },
+  HTTPS {
+    domain: Name,
+    priority: u16,
+    target: Name,
+    params: Vec<SvcParam>,
+    class: QueryClass,
+    ttl: u32
+  },
+  CAA {
+    domain: Name,
+    flags: u8,
+    tag: String,
+    value: Vec<u8>,
+    class: QueryClass,
+    ttl: u32
+  },
+  OPT {
+    edns: Edns
   }
 
 }
 
+// CAA tags are 1 to 15 ASCII letters and digits (RFC 8659 section 4.1)
+pub fn is_valid_caa_tag(tag: &[u8]) -> bool {
+  !tag.is_empty() && tag.len() <= 15 && tag.iter().all(u8::is_ascii_alphanumeric)
+}
+
 impl DnsRecord {
-  pub fn read(buffer: &mut BytePacketBuffer) -> Result<DnsRecord, Box<dyn std::error::Error>> {
-    let mut domain = String::new();
+  pub fn read<T: PacketBuffer>(buffer: &mut T) -> Result<DnsRecord, ProtocolError> {
+    let mut domain = Name::root();
     buffer.read_qname(&mut domain)?;
 
     let qtype_num = buffer.read_two_bytes()?;
     let qtype = QueryType::from_num(qtype_num);
-    let _ = buffer.read_two_bytes()?;
+    let class_num = buffer.read_two_bytes()?;
+    let class = QueryClass::from_num(class_num);
     let ttl = buffer.read_four_bytes()?;
     let data_length = buffer.read_two_bytes()?;
+    let rdata_start = buffer.current_positon_in_buffer();
+
+    // Every field is read through a window ending at RDLENGTH
+    let buffer = &mut RdataBuffer::new(buffer, data_length);
 
-    match qtype {
+    let record = match qtype {
       QueryType::A => {
             let raw_addr = buffer.read_four_bytes()?;
             let addrr = Ipv4Addr::new(
@@ -60,29 +158,66 @@ impl DnsRecord {
               ((raw_addr >> 8) & 0xFF) as u8,
               ((raw_addr ) & 0xFF) as u8,
             );
-            Ok(DnsRecord::A { domain, addr: addrr, ttl })
+            Ok(DnsRecord::A { domain, addr: addrr, class, ttl })
           }
 
       QueryType::MX => {
         let priority  = buffer.read_two_bytes()?;
-        let mut mx = String::new();
+        let mut mx = Name::root();
         buffer.read_qname(&mut mx)?;
         
-        Ok(DnsRecord::MX { domain, priority, host: mx, ttl })
+        Ok(DnsRecord::MX { domain, priority, host: mx, class, ttl })
       }
 
       QueryType::CNAME => {
-        let mut cname = String::new();
+        let mut cname = Name::root();
         buffer.read_qname(&mut cname)?;
 
-        Ok(DnsRecord::CNAME { domain, host: cname, ttl })
+        Ok(DnsRecord::CNAME { domain, host: cname, class, ttl })
+      }
+
+      QueryType::SOA => {
+        let mut mname = Name::root();
+        buffer.read_qname(&mut mname)?;
+
+        let mut rname = Name::root();
+        buffer.read_qname(&mut rname)?;
+
+        let serial = buffer.read_four_bytes()?;
+        let refresh = buffer.read_four_bytes()?;
+        let retry = buffer.read_four_bytes()?;
+        let expire = buffer.read_four_bytes()?;
+        let minimum = buffer.read_four_bytes()?;
+
+        Ok(DnsRecord::SOA { domain, mname, rname, serial, refresh, retry, expire, minimum, class, ttl })
+      }
+
+      QueryType::PTR => {
+        let mut ptr = Name::root();
+        buffer.read_qname(&mut ptr)?;
+
+        Ok(DnsRecord::PTR { domain, host: ptr, class, ttl })
       }
 
       QueryType::NS => {
-        let mut ns = String::new();
+        let mut ns = Name::root();
         buffer.read_qname(&mut ns)?;
 
-        Ok(DnsRecord::NS { domain, host: ns, ttl })
+        Ok(DnsRecord::NS { domain, host: ns, class, ttl })
+      }
+
+      QueryType::TXT => {
+        let mut data = Vec::new();
+
+        while buffer.remaining() > 0 {
+          let len = buffer.read_single_byte()? as usize;
+          let string = buffer.get_range(buffer.current_positon_in_buffer(), len)?.to_vec();
+          buffer.move_buffer(len)?;
+
+          data.push(string);
+        }
+
+        Ok(DnsRecord::TXT { domain, data, class, ttl })
       }
 
       QueryType::AAAA => {
@@ -92,38 +227,104 @@ impl DnsRecord {
         let raw_addr4 = buffer.read_four_bytes()?;
         let addr = Ipv6Addr::new(
           ((raw_addr1 >> 16) & 0xFFFF) as  u16, 
-          ((raw_addr1 >> 0) & 0xFFFF) as  u16, 
+          (raw_addr1 & 0xFFFF) as  u16, 
           ((raw_addr2 >> 16) & 0xFFFF) as  u16, 
-          ((raw_addr2 >> 0) & 0xFFFF) as  u16, 
-          ((raw_addr3 >> 16) & 0xFFFF) as  u16, 
-          ((raw_addr3 >> 0) & 0xFFFF) as  u16, 
+          (raw_addr2 & 0xFFFF) as  u16, 
           ((raw_addr3 >> 16) & 0xFFFF) as  u16, 
-          ((raw_addr3 >> 0) & 0xFFFF) as  u16, 
+          (raw_addr3 & 0xFFFF) as  u16, 
+          ((raw_addr4 >> 16) & 0xFFFF) as  u16, 
+          (raw_addr4 & 0xFFFF) as  u16, 
           );
-          Ok(DnsRecord::AAAA { domain, addr, ttl })
+          Ok(DnsRecord::AAAA { domain, addr, class, ttl })
+      }
+      QueryType::SRV => {
+        let priority = buffer.read_two_bytes()?;
+        let weight = buffer.read_two_bytes()?;
+        let port = buffer.read_two_bytes()?;
+
+        let mut target = Name::root();
+        buffer.read_qname(&mut target)?;
+
+        Ok(DnsRecord::SRV { domain, priority, weight, port, target, class, ttl })
+      }
+      QueryType::SSHFP => {
+        let algorithm = buffer.read_single_byte()?;
+        let fingerprint_type = buffer.read_single_byte()?;
+        let fingerprint = buffer.read_bytes(buffer.remaining())?;
+
+        Ok(DnsRecord::SSHFP { domain, algorithm, fingerprint_type, fingerprint, class, ttl })
+      }
+      QueryType::TLSA => {
+        let usage = buffer.read_single_byte()?;
+        let selector = buffer.read_single_byte()?;
+        let matching_type = buffer.read_single_byte()?;
+        let data = buffer.read_bytes(buffer.remaining())?;
+
+        Ok(DnsRecord::TLSA { domain, usage, selector, matching_type, data, class, ttl })
+      }
+      QueryType::SVCB | QueryType::HTTPS => {
+        let priority = buffer.read_two_bytes()?;
+
+        let mut target = Name::root();
+        buffer.read_qname(&mut target)?;
+
+        let params = read_params(buffer, buffer.remaining())?;
+
+        if qtype == QueryType::SVCB {
+          Ok(DnsRecord::SVCB { domain, priority, target, params, class, ttl })
+        } else {
+          Ok(DnsRecord::HTTPS { domain, priority, target, params, class, ttl })
+        }
+      }
+      QueryType::CAA => {
+        let flags = buffer.read_single_byte()?;
+        let tag_length = buffer.read_single_byte()? as usize;
+        let tag_start = buffer.current_positon_in_buffer();
+        let tag = buffer.read_bytes(tag_length)?;
+        // Checked so the tag goes back out exactly as it came in
+        if !is_valid_caa_tag(&tag) {
+          return Err(ProtocolError::BadRdata { offset: tag_start, reason: "CAA tag is not 1 to 15 letters and digits" });
+        }
+        let tag = String::from_utf8(tag).expect("CAA tags are ASCII");
+        let value = buffer.read_bytes(buffer.remaining())?;
+
+        Ok(DnsRecord::CAA { domain, flags, tag, value, class, ttl })
+      }
+      QueryType::OPT => {
+        let edns = Edns::read(buffer, class_num, ttl, data_length)?;
+
+        Ok(DnsRecord::OPT { edns })
       }
       QueryType::UNKNOWN(_) => {
-        buffer.move_buffer(data_length as usize)?;
+        let data = buffer.read_bytes(data_length as usize)?;
 
         Ok(DnsRecord::UNKNOWN { 
           domain, 
           qtype: qtype_num, 
-          data_length, 
+          data, 
+          class,
           ttl 
         })
       }
+    }?;
+
+    // The fields have to fill the rdata exactly, anything else would leave the rest of the
+    // message to be read out of step
+    if buffer.remaining() != 0 {
+      return Err(ProtocolError::BadRdataLength { offset: rdata_start, length: data_length });
     }
 
+    Ok(record)
   }
 
-  pub fn write(&self, buffer: &mut BytePacketBuffer) -> Result<usize, Box<dyn std::error::Error>> {
-    let start_pos = buffer.pos;
+  pub fn write<T: PacketBuffer>(&self, buffer: &mut T) -> Result<usize, ProtocolError> {
+    let start_pos = buffer.current_positon_in_buffer();
 
     match *self {
-      DnsRecord::A { ref domain, ref addr, ttl } => {
+      DnsRecord::A { ref domain, ref addr, class, ttl } => {
         buffer.write_qname(domain)?;
         buffer.write_u16(QueryType::A.to_num())?;
-        buffer.write_u16(1)?;
+        buffer.write_u16(class.to_num())?;
         buffer.write_u32(ttl)?;
         buffer.write_u16(4)?;
 
@@ -134,57 +335,114 @@ impl DnsRecord {
         buffer.write_u8(octets[3])?;
       }
 
-      DnsRecord::MX { ref domain, priority, ref host, ttl } => {
+      DnsRecord::MX { ref domain, priority, ref host, class, ttl } => {
         buffer.write_qname(domain)?;
         buffer.write_u16(QueryType::MX.to_num())?;
-        buffer.write_u16(1)?;
+        buffer.write_u16(class.to_num())?;
         buffer.write_u32(ttl)?;
 
-        let pos = buffer.pos;
-        buffer.write(0)?;
+        let pos = buffer.current_positon_in_buffer();
+        buffer.write_u16(0)?;
 
         buffer.write_u16(priority)?;
         buffer.write_qname(host)?;
 
-        let size = buffer.pos - (pos + 2);
-        buffer.set_u16(pos, size as u16)?;
+        buffer.set_length(pos)?;
 
       }
 
-      DnsRecord::CNAME { ref domain, ref host, ttl } => {
+      DnsRecord::CNAME { ref domain, ref host, class, ttl } => {
         buffer.write_qname(domain)?;
         buffer.write_u16(QueryType::CNAME.to_num())?;
-        buffer.write_u16(1)?;
+        buffer.write_u16(class.to_num())?;
+        buffer.write_u32(ttl)?;
+
+        let pos = buffer.current_positon_in_buffer();
+        buffer.write_u16(0)?;
+
+        buffer.write_qname(host)?;
+
+        buffer.set_length(pos)?;
+      }
+
+      DnsRecord::SOA { ref domain, ref mname, ref rname, serial, refresh, retry, expire, minimum, class, ttl } => {
+        buffer.write_qname(domain)?;
+        buffer.write_u16(QueryType::SOA.to_num())?;
+        buffer.write_u16(class.to_num())?;
         buffer.write_u32(ttl)?;
 
-        let pos = buffer.pos;
+        let pos = buffer.current_positon_in_buffer();
+        buffer.write_u16(0)?;
+
+        buffer.write_qname(mname)?;
+        buffer.write_qname(rname)?;
+        buffer.write_u32(serial)?;
+        buffer.write_u32(refresh)?;
+        buffer.write_u32(retry)?;
+        buffer.write_u32(expire)?;
+        buffer.write_u32(minimum)?;
+
+        buffer.set_length(pos)?;
+      }
+
+      DnsRecord::PTR { ref domain, ref host, class, ttl } => {
+        buffer.write_qname(domain)?;
+        buffer.write_u16(QueryType::PTR.to_num())?;
+        buffer.write_u16(class.to_num())?;
+        buffer.write_u32(ttl)?;
+
+        let pos = buffer.current_positon_in_buffer();
         buffer.write_u16(0)?;
 
         buffer.write_qname(host)?;
 
-        let size = buffer.pos - (pos + 2);
-        buffer.set_u16(pos, size as u16)?;
+        buffer.set_length(pos)?;
       }
 
-      DnsRecord::NS { ref domain, ref host, ttl } => {
+      DnsRecord::NS { ref domain, ref host, class, ttl } => {
         buffer.write_qname(domain)?;
         buffer.write_u16(QueryType::NS.to_num())?;
-        buffer.write_u16(1)?;
+        buffer.write_u16(class.to_num())?;
         buffer.write_u32(ttl)?;
 
-        let pos = buffer.pos;
+        let pos = buffer.current_positon_in_buffer();
         buffer.write_u16(0)?;
 
         buffer.write_qname(host)?;
 
-        let size = buffer.pos - (pos + 2);
-        buffer.set_u16(pos, size as u16)?;
+        buffer.set_length(pos)?;
       }
 
-      DnsRecord::AAAA { ref domain, ref addr, ttl } => {
+      DnsRecord::TXT { ref domain, ref data, class, ttl } => {
+        buffer.write_qname(domain)?;
+        buffer.write_u16(QueryType::TXT.to_num())?;
+        buffer.write_u16(class.to_num())?;
+        buffer.write_u32(ttl)?;
+
+        let pos = buffer.current_positon_in_buffer();
+        buffer.write_u16(0)?;
+
+        for string in data {
+          // A character-string holds at most 255 bytes, longer values are split across several
+          if string.is_empty() {
+            buffer.write_u8(0)?;
+          }
+
+          for chunk in string.chunks(255) {
+            buffer.write_u8(chunk.len() as u8)?;
+            for byte in chunk {
+              buffer.write_u8(*byte)?;
+            }
+          }
+        }
+
+        buffer.set_length(pos)?;
+      }
+
+      DnsRecord::AAAA { ref domain, ref addr, class, ttl } => {
         buffer.write_qname(domain)?;
         buffer.write_u16(QueryType::AAAA.to_num())?;
-        buffer.write_u16(1)?;
+        buffer.write_u16(class.to_num())?;
         buffer.write_u32(ttl)?;
         buffer.write_u16(16)?;
 
@@ -192,10 +450,354 @@ impl DnsRecord {
           buffer.write_u16(*octet)?;
         }
       }
-      DnsRecord::UNKNOWN { .. } => {
-        println!("Skipping record: {:?}", self)
+      DnsRecord::SRV { ref domain, priority, weight, port, ref target, class, ttl } => {
+        buffer.write_qname(domain)?;
+        buffer.write_u16(QueryType::SRV.to_num())?;
+        buffer.write_u16(class.to_num())?;
+        buffer.write_u32(ttl)?;
+
+        let pos = buffer.current_positon_in_buffer();
+        buffer.write_u16(0)?;
+
+        buffer.write_u16(priority)?;
+        buffer.write_u16(weight)?;
+        buffer.write_u16(port)?;
+        // The target must not be compressed (RFC 2782)
+        buffer.write_qname_uncompressed(target)?;
+
+        buffer.set_length(pos)?;
+      }
+      DnsRecord::SSHFP { ref domain, algorithm, fingerprint_type, ref fingerprint, class, ttl } => {
+        buffer.write_qname(domain)?;
+        buffer.write_u16(QueryType::SSHFP.to_num())?;
+        buffer.write_u16(class.to_num())?;
+        buffer.write_u32(ttl)?;
+
+        let pos = buffer.current_positon_in_buffer();
+        buffer.write_u16(0)?;
+
+        buffer.write_u8(algorithm)?;
+        buffer.write_u8(fingerprint_type)?;
+        for byte in fingerprint {
+          buffer.write_u8(*byte)?;
+        }
+
+        buffer.set_length(pos)?;
+      }
+      DnsRecord::TLSA { ref domain, usage, selector, matching_type, ref data, class, ttl } => {
+        buffer.write_qname(domain)?;
+        buffer.write_u16(QueryType::TLSA.to_num())?;
+        buffer.write_u16(class.to_num())?;
+        buffer.write_u32(ttl)?;
+
+        let pos = buffer.current_positon_in_buffer();
+        buffer.write_u16(0)?;
+
+        buffer.write_u8(usage)?;
+        buffer.write_u8(selector)?;
+        buffer.write_u8(matching_type)?;
+        for byte in data {
+          buffer.write_u8(*byte)?;
+        }
+
+        buffer.set_length(pos)?;
+      }
+      DnsRecord::SVCB { ref domain, priority, ref target, ref params, class, ttl }
+      | DnsRecord::HTTPS { ref domain, priority, ref target, ref params, class, ttl } => {
+        buffer.write_qname(domain)?;
+        buffer.write_u16(self.query_type().to_num())?;
+        buffer.write_u16(class.to_num())?;
+        buffer.write_u32(ttl)?;
+
+        let pos = buffer.current_positon_in_buffer();
+        buffer.write_u16(0)?;
+
+        buffer.write_u16(priority)?;
+        // The target must not be compressed (RFC 9460 section 2.2)
+        buffer.write_qname_uncompressed(target)?;
+        write_params(buffer, params)?;
+
+        buffer.set_length(pos)?;
+      }
+      DnsRecord::CAA { ref domain, flags, ref tag, ref value, class, ttl } => {
+        buffer.write_qname(domain)?;
+        buffer.write_u16(QueryType::CAA.to_num())?;
+        buffer.write_u16(class.to_num())?;
+        buffer.write_u32(ttl)?;
+
+        let pos = buffer.current_positon_in_buffer();
+        buffer.write_u16(0)?;
+
+        let tag_length = u8::try_from(tag.len())
+          .map_err(|_| ProtocolError::BadRdata { offset: pos, reason: "CAA tag longer than 255 octets" })?;
+        buffer.write_u8(flags)?;
+        buffer.write_u8(tag_length)?;
+        for byte in tag.bytes().chain(value.iter().copied()) {
+          buffer.write_u8(byte)?;
+        }
+
+        buffer.set_length(pos)?;
+      }
+      DnsRecord::OPT { ref edns } => {
+        edns.write(buffer)?;
+      }
+      DnsRecord::UNKNOWN { ref domain, qtype, ref data, class, ttl } => {
+        buffer.write_qname(domain)?;
+        buffer.write_u16(qtype)?;
+        buffer.write_u16(class.to_num())?;
+        buffer.write_u32(ttl)?;
+
+        let pos = buffer.current_positon_in_buffer();
+        buffer.write_u16(0)?;
+
+        for byte in data {
+          buffer.write_u8(*byte)?;
+        }
+
+        buffer.set_length(pos)?;
       }
     }
-    Ok(buffer.pos - start_pos)
+    Ok(buffer.current_positon_in_buffer() - start_pos)
   }
-}
\ No newline at end of file
+
+  // The owner name of the record
+  pub fn domain(&self) -> &Name {
+    match self {
+      DnsRecord::UNKNOWN { domain, .. }
+      | DnsRecord::A { domain, .. }
+      | DnsRecord::NS { domain, .. }
+      | DnsRecord::CNAME { domain, .. }
+      | DnsRecord::SOA { domain, .. }
+      | DnsRecord::PTR { domain, .. }
+      | DnsRecord::MX { domain, .. }
+      | DnsRecord::TXT { domain, .. }
+      | DnsRecord::AAAA { domain, .. }
+      | DnsRecord::SRV { domain, .. }
+      | DnsRecord::SSHFP { domain, .. }
+      | DnsRecord::TLSA { domain, .. }
+      | DnsRecord::SVCB { domain, .. }
+      | DnsRecord::HTTPS { domain, .. }
+      | DnsRecord::CAA { domain, .. } => domain,
+      DnsRecord::OPT { .. } => &ROOT,
+    }
+  }
+
+  pub fn query_type(&self) -> QueryType {
+    match self {
+      DnsRecord::UNKNOWN { qtype, .. } => QueryType::UNKNOWN(*qtype),
+      DnsRecord::A { .. } => QueryType::A,
+      DnsRecord::NS { .. } => QueryType::NS,
+      DnsRecord::CNAME { .. } => QueryType::CNAME,
+      DnsRecord::SOA { .. } => QueryType::SOA,
+      DnsRecord::PTR { .. } => QueryType::PTR,
+      DnsRecord::MX { .. } => QueryType::MX,
+      DnsRecord::TXT { .. } => QueryType::TXT,
+      DnsRecord::AAAA { .. } => QueryType::AAAA,
+      DnsRecord::SRV { .. } => QueryType::SRV,
+      DnsRecord::SSHFP { .. } => QueryType::SSHFP,
+      DnsRecord::TLSA { .. } => QueryType::TLSA,
+      DnsRecord::SVCB { .. } => QueryType::SVCB,
+      DnsRecord::HTTPS { .. } => QueryType::HTTPS,
+      DnsRecord::CAA { .. } => QueryType::CAA,
+      DnsRecord::OPT { .. } => QueryType::OPT,
+    }
+  }
+
+  pub fn class(&self) -> QueryClass {
+    match self {
+      DnsRecord::UNKNOWN { class, .. }
+      | DnsRecord::A { class, .. }
+      | DnsRecord::NS { class, .. }
+      | DnsRecord::CNAME { class, .. }
+      | DnsRecord::SOA { class, .. }
+      | DnsRecord::PTR { class, .. }
+      | DnsRecord::MX { class, .. }
+      | DnsRecord::TXT { class, .. }
+      | DnsRecord::AAAA { class, .. }
+      | DnsRecord::SRV { class, .. }
+      | DnsRecord::SSHFP { class, .. }
+      | DnsRecord::TLSA { class, .. }
+      | DnsRecord::SVCB { class, .. }
+      | DnsRecord::HTTPS { class, .. }
+      | DnsRecord::CAA { class, .. } => *class,
+      // The CLASS field of OPT carries the payload size instead
+      DnsRecord::OPT { edns } => QueryClass::from_num(edns.udp_payload_size),
+    }
+  }
+
+  pub fn ttl(&self) -> u32 {
+    match self {
+      DnsRecord::UNKNOWN { ttl, .. }
+      | DnsRecord::A { ttl, .. }
+      | DnsRecord::NS { ttl, .. }
+      | DnsRecord::CNAME { ttl, .. }
+      | DnsRecord::SOA { ttl, .. }
+      | DnsRecord::PTR { ttl, .. }
+      | DnsRecord::MX { ttl, .. }
+      | DnsRecord::TXT { ttl, .. }
+      | DnsRecord::AAAA { ttl, .. }
+      | DnsRecord::SRV { ttl, .. }
+      | DnsRecord::SSHFP { ttl, .. }
+      | DnsRecord::TLSA { ttl, .. }
+      | DnsRecord::SVCB { ttl, .. }
+      | DnsRecord::HTTPS { ttl, .. }
+      | DnsRecord::CAA { ttl, .. } => *ttl,
+      DnsRecord::OPT { .. } => 0,
+    }
+  }
+
+  pub fn set_ttl(&mut self, new_ttl: u32) {
+    match self {
+      DnsRecord::UNKNOWN { ttl, .. }
+      | DnsRecord::A { ttl, .. }
+      | DnsRecord::NS { ttl, .. }
+      | DnsRecord::CNAME { ttl, .. }
+      | DnsRecord::SOA { ttl, .. }
+      | DnsRecord::PTR { ttl, .. }
+      | DnsRecord::MX { ttl, .. }
+      | DnsRecord::TXT { ttl, .. }
+      | DnsRecord::AAAA { ttl, .. }
+      | DnsRecord::SRV { ttl, .. }
+      | DnsRecord::SSHFP { ttl, .. }
+      | DnsRecord::TLSA { ttl, .. }
+      | DnsRecord::SVCB { ttl, .. }
+      | DnsRecord::HTTPS { ttl, .. }
+      | DnsRecord::CAA { ttl, .. } => *ttl = new_ttl,
+      DnsRecord::OPT { .. } => {},
+    }
+  }
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{protocol::byte_packet::VectorPacketBuffer, test_util::name};
+
+  fn round_trip(record: &DnsRecord) -> DnsRecord {
+    let mut buffer = VectorPacketBuffer::new();
+    record.write(&mut buffer).unwrap();
+    buffer.change_buffer_position(0).unwrap();
+    DnsRecord::read(&mut buffer).unwrap()
+  }
+
+  // Rdata that starts right after the fixed fields of an owner name `a.example.`
+  const RDATA_START: usize = 21;
+
+  // A record of `qtype` with the given RDLENGTH and rdata, followed by an A record for `b.`
+  // that a read past RDLENGTH would run into
+  fn raw_record(qtype: QueryType, rdlength: u16, rdata: &[u8]) -> VectorPacketBuffer {
+    let mut bytes = b"\x01a\x07example\x00".to_vec();
+    bytes.extend_from_slice(&qtype.to_num().to_be_bytes());
+    bytes.extend_from_slice(&[0x00, 0x01, 0x00, 0x00, 0x00, 0x3C]);
+    bytes.extend_from_slice(&rdlength.to_be_bytes());
+    bytes.extend_from_slice(rdata);
+    bytes.extend_from_slice(b"\x01b\x00\x00\x01\x00\x01\x00\x00\x00\x3C\x00\x04\xC0\x00\x02\x01");
+    VectorPacketBuffer::from_bytes(bytes)
+  }
+
+  fn read_raw(qtype: QueryType, rdlength: u16, rdata: &[u8]) -> Result<DnsRecord, ProtocolError> {
+    DnsRecord::read(&mut raw_record(qtype, rdlength, rdata))
+  }
+
+  #[test]
+  fn caa_tag_must_be_letters_and_digits() {
+    let record = read_raw(QueryType::CAA, 9, b"\x00\x05issue\x3B\x3B").unwrap();
+    assert!(matches!(record, DnsRecord::CAA { ref tag, .. } if tag == "issue"));
+
+    let bad_tag = Err(ProtocolError::BadRdata { offset: RDATA_START + 2, reason: "CAA tag is not 1 to 15 letters and digits" });
+    assert_eq!(read_raw(QueryType::CAA, 8, b"\x00\x05is\xFFue\x3B"), bad_tag);
+    assert_eq!(read_raw(QueryType::CAA, 7, b"\x00\x04is-u\x3B"), bad_tag);
+    assert_eq!(read_raw(QueryType::CAA, 3, b"\x00\x00\x3B"), bad_tag);
+  }
+
+  fn bad_length(rdlength: u16) -> Result<DnsRecord, ProtocolError> {
+    Err(ProtocolError::BadRdataLength { offset: RDATA_START, length: rdlength })
+  }
+
+  #[test]
+  fn rdata_matching_rdlength_is_read_and_the_next_record_follows() {
+    let mut buffer = raw_record(QueryType::CNAME, 5, b"\x03www\x00");
+    assert_eq!(DnsRecord::read(&mut buffer).unwrap(), DnsRecord::CNAME { domain: name("a.example"), host: name("www"), class: QueryClass::IN, ttl: 60 });
+    assert_eq!(DnsRecord::read(&mut buffer).unwrap().domain(), &name("b"));
+  }
+
+  #[test]
+  fn name_rdata_over_and_under_running_rdlength_is_rejected() {
+    // The name needs 5 octets
+    assert_eq!(read_raw(QueryType::CNAME, 3, b"\x03www\x00"), bad_length(3));
+    assert_eq!(read_raw(QueryType::NS, 7, b"\x03www\x00\x00\x00"), bad_length(7));
+    // A pointer back to the owner name still works inside the window
+    assert_eq!(read_raw(QueryType::PTR, 2, b"\xC0\x00").unwrap(), DnsRecord::PTR { domain: name("a.example"), host: name("a.example"), class: QueryClass::IN, ttl: 60 });
+    assert_eq!(read_raw(QueryType::PTR, 1, b"\xC0\x00"), bad_length(1));
+  }
+
+  #[test]
+  fn fixed_size_rdata_of_the_wrong_length_is_rejected() {
+    assert_eq!(read_raw(QueryType::A, 3, b"\xC0\x00\x02"), bad_length(3));
+    assert_eq!(read_raw(QueryType::A, 5, b"\xC0\x00\x02\x01\x00"), bad_length(5));
+  }
+
+  #[test]
+  fn txt_strings_running_past_rdlength_are_rejected() {
+    // The second string claims 5 octets where only 1 is left
+    assert_eq!(read_raw(QueryType::TXT, 8, b"\x05hello\x05x"), bad_length(8));
+    // RDLENGTH ends in the middle of the only string
+    assert_eq!(read_raw(QueryType::TXT, 4, b"\x05hello"), bad_length(4));
+    assert_eq!(read_raw(QueryType::TXT, 7, b"\x05hello\x00").unwrap(), DnsRecord::TXT {
+      domain: name("a.example"),
+      data: vec![b"hello".to_vec(), Vec::new()],
+      class: QueryClass::IN,
+      ttl: 60,
+    });
+  }
+
+  #[test]
+  fn svcb_params_running_past_rdlength_are_rejected() {
+    // Priority 1, target `.`, port=443
+    let rdata = b"\x00\x01\x00\x00\x03\x00\x02\x01\xBB";
+    assert!(matches!(read_raw(QueryType::SVCB, 9, rdata).unwrap(), DnsRecord::SVCB { ref params, .. } if params == &[SvcParam::Port(443)]));
+
+    // The port value is cut off by RDLENGTH
+    assert_eq!(read_raw(QueryType::SVCB, 8, rdata), bad_length(8));
+    // RDLENGTH ends inside the key and length of a param
+    assert_eq!(read_raw(QueryType::HTTPS, 11, b"\x00\x01\x00\x00\x03\x00\x02\x01\xBB\x00\x01"), bad_length(11));
+    // The target name runs past RDLENGTH
+    assert_eq!(read_raw(QueryType::HTTPS, 3, b"\x00\x01\x03www\x00"), bad_length(3));
+  }
+
+  #[test]
+  fn opt_options_running_past_rdlength_are_rejected() {
+    // A cookie option with 8 octets of data
+    let rdata = b"\x00\x0A\x00\x08\x01\x02\x03\x04\x05\x06\x07\x08";
+    assert!(matches!(read_raw(QueryType::OPT, 12, rdata).unwrap(), DnsRecord::OPT { ref edns } if edns.options.len() == 1));
+
+    // The option claims more data than the rdata holds
+    assert_eq!(read_raw(QueryType::OPT, 10, rdata), bad_length(10));
+    // Leftover bytes too short to be an option header
+    assert_eq!(read_raw(QueryType::OPT, 14, b"\x00\x0A\x00\x08\x01\x02\x03\x04\x05\x06\x07\x08\x00\x0A"), bad_length(14));
+  }
+
+  #[test]
+  fn rdata_too_long_for_rdlength_is_an_error() {
+    let records = [
+      DnsRecord::SSHFP { domain: name("a.example"), algorithm: 1, fingerprint_type: 2, fingerprint: vec![0; 65534], class: QueryClass::IN, ttl: 60 },
+      DnsRecord::TLSA { domain: name("a.example"), usage: 3, selector: 1, matching_type: 1, data: vec![0; 65533], class: QueryClass::IN, ttl: 60 },
+      DnsRecord::CAA { domain: name("a.example"), flags: 0, tag: "issue".to_string(), value: vec![b'x'; 65530], class: QueryClass::IN, ttl: 60 },
+      DnsRecord::UNKNOWN { domain: name("a.example"), qtype: 65534, data: vec![0; 65536], class: QueryClass::IN, ttl: 60 },
+      DnsRecord::TXT { domain: name("a.example"), data: vec![vec![b'x'; 65280]], class: QueryClass::IN, ttl: 60 },
+    ];
+
+    for record in records {
+      let mut buffer = VectorPacketBuffer::new();
+      assert!(matches!(record.write(&mut buffer), Err(ProtocolError::RdataTooLong { .. })), "{:?}", record.query_type());
+    }
+  }
+
+  #[test]
+  fn rdata_at_the_rdlength_limit_round_trips() {
+    let record = DnsRecord::SSHFP { domain: name("a.example"), algorithm: 1, fingerprint_type: 2, fingerprint: vec![7; 65533], class: QueryClass::IN, ttl: 60 };
+    assert_eq!(round_trip(&record), record);
+
+    let record = DnsRecord::CAA { domain: name("a.example"), flags: 128, tag: "iodef".to_string(), value: b"mailto:ca@example".to_vec(), class: QueryClass::IN, ttl: 60 };
+    assert_eq!(round_trip(&record), record);
+  }
+}
@@ -0,0 +1,159 @@
+use std::fmt;
+
+use super::edns::EdnsOption;
+
+// Option code of an Extended DNS Error in the OPT record
+pub const EDE_OPTION_CODE: u16 = 15;
+
+// INFO-CODEs of Extended DNS Errors (RFC 8914 section 4)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ExtendedErrorCode {
+  Other, //0
+  UnsupportedDnskeyAlgorithm, //1
+  UnsupportedDsDigestType, //2
+  StaleAnswer, //3
+  ForgedAnswer, //4
+  DnssecIndeterminate, //5
+  DnssecBogus, //6
+  SignatureExpired, //7
+  SignatureNotYetValid, //8
+  DnskeyMissing, //9
+  RrsigsMissing, //10
+  NoZoneKeyBitSet, //11
+  NsecMissing, //12
+  CachedError, //13
+  NotReady, //14
+  Blocked, //15
+  Censored, //16
+  Filtered, //17
+  Prohibited, //18
+  StaleNxdomainAnswer, //19
+  NotAuthoritative, //20
+  NotSupported, //21
+  NoReachableAuthority, //22
+  NetworkError, //23
+  InvalidData, //24
+  Unknown(u16),
+}
+
+const NAMES: [&str; 25] = [
+  "Other",
+  "Unsupported DNSKEY Algorithm",
+  "Unsupported DS Digest Type",
+  "Stale Answer",
+  "Forged Answer",
+  "DNSSEC Indeterminate",
+  "DNSSEC Bogus",
+  "Signature Expired",
+  "Signature Not Yet Valid",
+  "DNSKEY Missing",
+  "RRSIGs Missing",
+  "No Zone Key Bit Set",
+  "NSEC Missing",
+  "Cached Error",
+  "Not Ready",
+  "Blocked",
+  "Censored",
+  "Filtered",
+  "Prohibited",
+  "Stale NXDOMAIN Answer",
+  "Not Authoritative",
+  "Not Supported",
+  "No Reachable Authority",
+  "Network Error",
+  "Invalid Data",
+];
+
+const CODES: [ExtendedErrorCode; 25] = [
+  ExtendedErrorCode::Other,
+  ExtendedErrorCode::UnsupportedDnskeyAlgorithm,
+  ExtendedErrorCode::UnsupportedDsDigestType,
+  ExtendedErrorCode::StaleAnswer,
+  ExtendedErrorCode::ForgedAnswer,
+  ExtendedErrorCode::DnssecIndeterminate,
+  ExtendedErrorCode::DnssecBogus,
+  ExtendedErrorCode::SignatureExpired,
+  ExtendedErrorCode::SignatureNotYetValid,
+  ExtendedErrorCode::DnskeyMissing,
+  ExtendedErrorCode::RrsigsMissing,
+  ExtendedErrorCode::NoZoneKeyBitSet,
+  ExtendedErrorCode::NsecMissing,
+  ExtendedErrorCode::CachedError,
+  ExtendedErrorCode::NotReady,
+  ExtendedErrorCode::Blocked,
+  ExtendedErrorCode::Censored,
+  ExtendedErrorCode::Filtered,
+  ExtendedErrorCode::Prohibited,
+  ExtendedErrorCode::StaleNxdomainAnswer,
+  ExtendedErrorCode::NotAuthoritative,
+  ExtendedErrorCode::NotSupported,
+  ExtendedErrorCode::NoReachableAuthority,
+  ExtendedErrorCode::NetworkError,
+  ExtendedErrorCode::InvalidData,
+];
+
+impl ExtendedErrorCode {
+  pub fn to_num(self) -> u16 {
+    match self {
+      ExtendedErrorCode::Unknown(code) => code,
+      code => CODES.iter().position(|known| *known == code).unwrap_or(0) as u16,
+    }
+  }
+
+  pub fn from_num(num: u16) -> ExtendedErrorCode {
+    CODES.get(num as usize).copied().unwrap_or(ExtendedErrorCode::Unknown(num))
+  }
+}
+
+impl fmt::Display for ExtendedErrorCode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match NAMES.get(self.to_num() as usize) {
+      Some(name) if !matches!(self, ExtendedErrorCode::Unknown(_)) => write!(f, "{}", name),
+      _ => write!(f, "Unknown"),
+    }
+  }
+}
+
+// An Extended DNS Error, telling the client why a response is what it is (RFC 8914)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtendedError {
+  pub code: ExtendedErrorCode,
+  pub text: String,
+}
+
+impl ExtendedError {
+  pub fn new(code: ExtendedErrorCode, text: &str) -> Self {
+    Self { code, text: text.to_string() }
+  }
+
+  // Decode an EDNS option, `None` when it is not an Extended DNS Error
+  pub fn from_option(option: &EdnsOption) -> Option<ExtendedError> {
+    if option.code != EDE_OPTION_CODE || option.data.len() < 2 {
+      return None;
+    }
+
+    Some(ExtendedError {
+      code: ExtendedErrorCode::from_num(u16::from_be_bytes([option.data[0], option.data[1]])),
+      // EXTRA-TEXT is UTF-8 and may be NUL terminated by sloppy senders
+      text: String::from_utf8_lossy(&option.data[2..]).trim_end_matches('\0').to_string(),
+    })
+  }
+
+  pub fn to_option(&self) -> EdnsOption {
+    let mut data = self.code.to_num().to_be_bytes().to_vec();
+    data.extend_from_slice(self.text.as_bytes());
+
+    EdnsOption { code: EDE_OPTION_CODE, data }
+  }
+}
+
+// Shown the way dig does, e.g. `22 (No Reachable Authority): (at delegation example.)`
+impl fmt::Display for ExtendedError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} ({})", self.code.to_num(), self.code)?;
+    if !self.text.is_empty() {
+      write!(f, ": ({})", self.text)?;
+    }
+    Ok(())
+  }
+}
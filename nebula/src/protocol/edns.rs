@@ -0,0 +1,104 @@
+use super::{byte_packet::{PacketBuffer, RdataBuffer, MAX_EDNS_PACKET_SIZE}, ede::ExtendedError, error::ProtocolError};
+
+// Payload size we advertise in our own OPT records
+pub const DEFAULT_UDP_PAYLOAD_SIZE: u16 = MAX_EDNS_PACKET_SIZE as u16;
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
+pub struct EdnsOption {
+  pub code: u16,
+  pub data: Vec<u8>,
+}
+
+// The EDNS(0) OPT pseudo-record (RFC 6891). The CLASS field of the record holds the
+// requestor's UDP payload size and the TTL field holds the extended RCODE, version and flags.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
+pub struct Edns {
+  pub udp_payload_size: u16,
+  pub extended_rcode: u8,
+  pub version: u8,
+  pub dnssec_ok: bool,
+  pub options: Vec<EdnsOption>,
+}
+
+impl Edns {
+  pub fn new() -> Self {
+    Self {
+      udp_payload_size: DEFAULT_UDP_PAYLOAD_SIZE,
+      extended_rcode: 0,
+      version: 0,
+      dnssec_ok: false,
+      options: Vec::new(),
+    }
+  }
+
+  // Build from the already parsed CLASS and TTL fields, then read the options in the rdata. An
+  // option running past the end of the rdata is an error rather than a read into the next record.
+  pub fn read<T: PacketBuffer>(buffer: &mut T, class: u16, ttl: u32, data_length: u16) -> Result<Edns, ProtocolError> {
+    let mut options = Vec::new();
+    let buffer = &mut RdataBuffer::new(buffer, data_length);
+
+    while buffer.remaining() > 0 {
+      let code = buffer.read_two_bytes()?;
+      let length = buffer.read_two_bytes()? as usize;
+      let data = buffer.get_range(buffer.current_positon_in_buffer(), length)?.to_vec();
+      buffer.move_buffer(length)?;
+
+      options.push(EdnsOption { code, data });
+    }
+
+    Ok(Edns {
+      udp_payload_size: class,
+      extended_rcode: (ttl >> 24) as u8,
+      version: ((ttl >> 16) & 0xFF) as u8,
+      dnssec_ok: (ttl & 0x8000) > 0,
+      options,
+    })
+  }
+
+  // Write the full OPT record, owner name included
+  pub fn write<T: PacketBuffer>(&self, buffer: &mut T) -> Result<(), ProtocolError> {
+    // The owner name is always the root
+    buffer.write_u8(0)?;
+    buffer.write_u16(super::query_type::QueryType::OPT.to_num())?;
+    buffer.write_u16(self.udp_payload_size)?;
+    buffer.write_u32(self.ttl())?;
+
+    let pos = buffer.current_positon_in_buffer();
+    buffer.write_u16(0)?;
+
+    for option in &self.options {
+      buffer.write_u16(option.code)?;
+
+      let option_pos = buffer.current_positon_in_buffer();
+      buffer.write_u16(0)?;
+      for byte in &option.data {
+        buffer.write_u8(*byte)?;
+      }
+      buffer.set_length(option_pos)?;
+    }
+
+    buffer.set_length(pos)?;
+
+    Ok(())
+  }
+
+  pub fn add_extended_error(&mut self, error: ExtendedError) {
+    self.options.push(error.to_option());
+  }
+
+  pub fn extended_errors(&self) -> Vec<ExtendedError> {
+    self.options.iter().filter_map(ExtendedError::from_option).collect()
+  }
+
+  fn ttl(&self) -> u32 {
+    ((self.extended_rcode as u32) << 24)
+      | ((self.version as u32) << 16)
+      | ((self.dnssec_ok as u32) << 15)
+  }
+}
+
+impl Default for Edns {
+  fn default() -> Self {
+    Self::new()
+  }
+}
@@ -0,0 +1,58 @@
+use std::{error::Error, fmt};
+
+// Everything that can go wrong reading or writing the wire format. Offsets are positions in the
+// message, so a malformed packet can be told apart from an I/O or upstream failure and located.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolError {
+  // A read ran past the end of the message
+  Truncated { offset: usize },
+  // A write ran past the end of a fixed size buffer
+  BufferFull { offset: usize },
+  // A label type other than a plain label or a compression pointer (RFC 6891 section 5)
+  BadLabelType { offset: usize, byte: u8 },
+  // A label longer than 63 octets
+  LabelTooLong { offset: usize, length: usize },
+  // A name longer than 255 octets in wire format
+  NameTooLong { offset: usize },
+  // Two dots in a row in a name written in presentation format
+  EmptyLabel { offset: usize },
+  // A backslash not followed by a character or by three digits up to 255
+  BadEscape { offset: usize },
+  // A Unicode name that can not be turned into A-labels
+  BadIdn { text: String },
+  // Compression pointers that jump around too often to be a real name
+  PointerLoop { offset: usize },
+  // The rdata does not fit the RDLENGTH of its record
+  BadRdataLength { offset: usize, length: u16 },
+  // Record data too long for the two byte RDLENGTH (or option length) in front of it
+  RdataTooLong { offset: usize, length: usize },
+  // Rdata whose fields are malformed for its type
+  BadRdata { offset: usize, reason: &'static str },
+  // Even the header and question do not fit in the space available
+  ResponseTooLarge { max_size: usize },
+  // An RCODE past the header's four bits in a packet without an OPT record to carry the rest
+  ExtendedRcodeWithoutEdns { rcode: u16 },
+}
+
+impl fmt::Display for ProtocolError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ProtocolError::Truncated { offset } => write!(f, "Message truncated at offset {}", offset),
+      ProtocolError::BufferFull { offset } => write!(f, "Buffer full at offset {}", offset),
+      ProtocolError::BadLabelType { offset, byte } => write!(f, "Unknown label type 0x{:02X} at offset {}", byte, offset),
+      ProtocolError::LabelTooLong { offset, length } => write!(f, "Label of {} octets at offset {} is too long", length, offset),
+      ProtocolError::NameTooLong { offset } => write!(f, "Name at offset {} is longer than 255 octets", offset),
+      ProtocolError::EmptyLabel { offset } => write!(f, "Empty label at offset {}", offset),
+      ProtocolError::BadEscape { offset } => write!(f, "Bad escape at offset {}", offset),
+      ProtocolError::BadIdn { text } => write!(f, "{} is not a valid internationalized name", text),
+      ProtocolError::PointerLoop { offset } => write!(f, "Compression pointer loop at offset {}", offset),
+      ProtocolError::BadRdataLength { offset, length } => write!(f, "Record data at offset {} does not match its length of {}", offset, length),
+      ProtocolError::RdataTooLong { offset, length } => write!(f, "Record data of {} octets at offset {} does not fit its length field", length, offset),
+      ProtocolError::BadRdata { offset, reason } => write!(f, "{} at offset {}", reason, offset),
+      ProtocolError::ResponseTooLarge { max_size } => write!(f, "Response does not fit in {} bytes", max_size),
+      ProtocolError::ExtendedRcodeWithoutEdns { rcode } => write!(f, "RCODE {} needs an OPT record", rcode),
+    }
+  }
+}
+
+impl Error for ProtocolError {}
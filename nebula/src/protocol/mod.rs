@@ -1,8 +1,16 @@
 pub mod byte_packet;
+pub mod error;
 pub mod res_code;
 pub mod dns_header;
+pub mod name;
+pub mod opcode;
+pub mod query_class;
 pub mod query_type;
 pub mod dns_question;
 pub mod dns_record;
 pub mod dns_packet;
-pub mod response_packet;
\ No newline at end of file
+pub mod edns;
+pub mod ede;
+pub mod presentation;
+pub mod reverse;
+pub mod svcb;
\ No newline at end of file
@@ -0,0 +1,423 @@
+use std::{cmp::Ordering, fmt, hash::{Hash, Hasher}, str::FromStr};
+
+use super::error::ProtocolError;
+
+// Longest label allowed (RFC 1035 section 2.3.4)
+pub const MAX_LABEL_LENGTH: usize = 63;
+// Longest name in wire format, length octets included (RFC 1035 section 3.1)
+pub const MAX_NAME_LENGTH: usize = 255;
+
+// A fully qualified domain name as a list of raw labels, the root being no labels at all.
+// Labels are arbitrary bytes and keep the case they were written with, but names compare, hash
+// and order case-insensitively for ASCII letters (RFC 4343).
+#[derive(Clone, Default)]
+pub struct Name {
+  labels: Vec<Vec<u8>>,
+}
+
+// Octets a list of labels takes in wire format, the root label included
+fn wire_length(labels: &[Vec<u8>]) -> usize {
+  labels.iter().map(|label| label.len() + 1).sum::<usize>() + 1
+}
+
+impl Name {
+  pub const fn root() -> Self {
+    Self { labels: Vec::new() }
+  }
+
+  // Build a name from raw labels, checking the label and name lengths. `offset` is where the name
+  // starts in the message, errors point at the offending label as if it was written uncompressed.
+  pub fn from_labels(labels: Vec<Vec<u8>>, offset: usize) -> Result<Name, ProtocolError> {
+    let mut label_offset = offset;
+    for label in &labels {
+      if label.is_empty() {
+        return Err(ProtocolError::EmptyLabel { offset: label_offset });
+      }
+      if label.len() > MAX_LABEL_LENGTH {
+        return Err(ProtocolError::LabelTooLong { offset: label_offset, length: label.len() });
+      }
+      label_offset += label.len() + 1;
+    }
+
+    if wire_length(&labels) > MAX_NAME_LENGTH {
+      return Err(ProtocolError::NameTooLong { offset });
+    }
+
+    Ok(Self { labels })
+  }
+
+  // Parse a name in presentation format, where `\.` and `\DDD` stand for bytes that can not be
+  // written as they are. Names are always fully qualified, so the trailing dot is optional and
+  // both "" and "." are the root. Offsets in errors are byte offsets into `text`.
+  pub fn parse(text: &str) -> Result<Name, ProtocolError> {
+    let bytes = text.as_bytes();
+    if bytes == b"." {
+      return Ok(Name::root());
+    }
+
+    let mut labels = Vec::new();
+    let mut label = Vec::new();
+    let mut label_start = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+      match bytes[i] {
+        b'\\' => {
+          let digits = bytes.get(i + 1..i + 4).filter(|digits| digits.iter().all(u8::is_ascii_digit));
+
+          if let Some(digits) = digits {
+            let value = digits.iter().fold(0u16, |value, digit| value * 10 + (digit - b'0') as u16);
+            label.push(u8::try_from(value).map_err(|_| ProtocolError::BadEscape { offset: i })?);
+            i += 4;
+          } else {
+            label.push(*bytes.get(i + 1).ok_or(ProtocolError::BadEscape { offset: i })?);
+            i += 2;
+          }
+        }
+        b'.' => {
+          if label.is_empty() {
+            return Err(ProtocolError::EmptyLabel { offset: i });
+          }
+          labels.push(std::mem::take(&mut label));
+          i += 1;
+          label_start = i;
+        }
+        byte => {
+          label.push(byte);
+          i += 1;
+        }
+      }
+
+      if label.len() > MAX_LABEL_LENGTH {
+        return Err(ProtocolError::LabelTooLong { offset: label_start, length: label.len() });
+      }
+    }
+
+    if !label.is_empty() {
+      labels.push(label);
+    }
+
+    if wire_length(&labels) > MAX_NAME_LENGTH {
+      return Err(ProtocolError::NameTooLong { offset: 0 });
+    }
+
+    Ok(Self { labels })
+  }
+
+  // Parse a name that may be written in Unicode, such as `bücher.example`, turning its labels
+  // into `xn--` A-labels (IDNA 2008 with the UTS #46 mapping, so it is also lowercased). Names
+  // that are already ASCII are parsed as they are.
+  pub fn from_unicode(text: &str) -> Result<Name, ProtocolError> {
+    if text.is_ascii() {
+      return Name::parse(text);
+    }
+
+    let ascii = idna::domain_to_ascii(text).map_err(|_| ProtocolError::BadIdn { text: text.to_string() })?;
+    Name::parse(&ascii)
+  }
+
+  // Presentation format with every A-label shown as the Unicode it stands for, for display only
+  pub fn to_unicode(&self) -> String {
+    if self.is_root() {
+      return ".".to_string();
+    }
+
+    let mut text = String::new();
+    for label in &self.labels {
+      let unicode = label.get(..4)
+        .filter(|prefix| prefix.eq_ignore_ascii_case(b"xn--"))
+        .and_then(|_| std::str::from_utf8(label).ok())
+        .and_then(|ascii| match idna::domain_to_unicode(ascii) {
+          // Only labels that convert back to themselves are real A-labels
+          (unicode, Ok(())) if idna::domain_to_ascii(&unicode).is_ok_and(|back| back.eq_ignore_ascii_case(ascii)) => Some(unicode),
+          _ => None,
+        });
+
+      match unicode {
+        Some(unicode) => text.push_str(&unicode),
+        None => text.push_str(&EscapedLabel(label).to_string()),
+      }
+      text.push('.');
+    }
+
+    text
+  }
+
+  pub fn labels(&self) -> &[Vec<u8>] {
+    &self.labels
+  }
+
+  pub fn is_root(&self) -> bool {
+    self.labels.is_empty()
+  }
+
+  // Octets the name takes in wire format when written without compression
+  pub fn wire_length(&self) -> usize {
+    wire_length(&self.labels)
+  }
+
+  // Whether the name is `zone` itself or lies below it. Whole labels are compared, so
+  // `example.com` is not below `ample.com`, and every name is below the root.
+  pub fn is_subdomain_of(&self, zone: &Name) -> bool {
+    let Some(skip) = self.labels.len().checked_sub(zone.labels.len()) else {
+      return false;
+    };
+
+    self.labels[skip..].iter().zip(&zone.labels).all(|(a, b)| a.eq_ignore_ascii_case(b))
+  }
+
+  // The name with its first label removed, `None` for the root
+  pub fn parent(&self) -> Option<Name> {
+    if self.is_root() {
+      return None;
+    }
+
+    Some(Self { labels: self.labels[1..].to_vec() })
+  }
+}
+
+// A label in presentation format, with `\.` and `\DDD` escapes where needed
+struct EscapedLabel<'a>(&'a [u8]);
+
+impl fmt::Display for EscapedLabel<'_> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for byte in self.0 {
+      match byte {
+        b'.' | b'\\' | b'"' | b';' | b'(' | b')' | b'@' | b'$' => write!(f, "\\{}", *byte as char)?,
+        0x21..=0x7E => write!(f, "{}", *byte as char)?,
+        _ => write!(f, "\\{:03}", byte)?,
+      }
+    }
+
+    Ok(())
+  }
+}
+
+// Presentation format with the trailing dot, e.g. `www.example.com.`. The alternate form `{:#}`
+// shows A-labels as Unicode.
+impl fmt::Display for Name {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if f.alternate() {
+      return write!(f, "{}", self.to_unicode());
+    }
+
+    if self.is_root() {
+      return write!(f, ".");
+    }
+
+    for label in &self.labels {
+      write!(f, "{}.", EscapedLabel(label))?;
+    }
+
+    Ok(())
+  }
+}
+
+impl fmt::Debug for Name {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Name(\"{}\")", self)
+  }
+}
+
+impl FromStr for Name {
+  type Err = ProtocolError;
+
+  fn from_str(text: &str) -> Result<Name, ProtocolError> {
+    Name::parse(text)
+  }
+}
+
+impl PartialEq for Name {
+  fn eq(&self, other: &Self) -> bool {
+    self.labels.len() == other.labels.len()
+      && self.labels.iter().zip(&other.labels).all(|(a, b)| a.eq_ignore_ascii_case(b))
+  }
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    for label in &self.labels {
+      state.write_usize(label.len());
+      for byte in label {
+        state.write_u8(byte.to_ascii_lowercase());
+      }
+    }
+    state.write_usize(self.labels.len());
+  }
+}
+
+// Canonical DNS name order (RFC 4034 section 6.1), comparing from the rightmost label
+impl Ord for Name {
+  fn cmp(&self, other: &Self) -> Ordering {
+    for (a, b) in self.labels.iter().rev().zip(other.labels.iter().rev()) {
+      let ordering = a.iter().map(u8::to_ascii_lowercase).cmp(b.iter().map(u8::to_ascii_lowercase));
+      if ordering != Ordering::Equal {
+        return ordering;
+      }
+    }
+
+    self.labels.len().cmp(&other.labels.len())
+  }
+}
+
+impl PartialOrd for Name {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashSet;
+
+  use super::*;
+  use crate::test_util::name;
+
+  #[test]
+  fn parse_splits_labels_and_accepts_an_optional_trailing_dot() {
+    assert_eq!(name("www.example.com").labels(), &[b"www".to_vec(), b"example".to_vec(), b"com".to_vec()]);
+    assert_eq!(name("www.example.com."), name("www.example.com"));
+    assert!(name(".").is_root());
+    assert!(name("").is_root());
+  }
+
+  #[test]
+  fn parse_rejects_empty_labels() {
+    assert_eq!(Name::parse("www..com"), Err(ProtocolError::EmptyLabel { offset: 4 }));
+    assert_eq!(Name::parse(".com"), Err(ProtocolError::EmptyLabel { offset: 0 }));
+    assert_eq!(Name::parse("com.."), Err(ProtocolError::EmptyLabel { offset: 4 }));
+  }
+
+  #[test]
+  fn parse_resolves_escapes() {
+    assert_eq!(name(r"a\.b.example").labels(), &[b"a.b".to_vec(), b"example".to_vec()]);
+    assert_eq!(name(r"\065\000\255.example").labels()[0], vec![b'A', 0, 255]);
+    assert_eq!(name(r"back\\slash").labels()[0], b"back\\slash".to_vec());
+
+    assert_eq!(Name::parse(r"\256.example"), Err(ProtocolError::BadEscape { offset: 0 }));
+    assert_eq!(Name::parse("dangling\\"), Err(ProtocolError::BadEscape { offset: 8 }));
+  }
+
+  #[test]
+  fn display_escapes_what_parse_unescapes() {
+    for text in [r"a\.b.example.", r"\000\255.example.", r"\;\@.example.", "www.Example.COM."] {
+      assert_eq!(name(text).to_string(), text);
+      assert_eq!(name(&name(text).to_string()).labels(), name(text).labels());
+    }
+    assert_eq!(Name::root().to_string(), ".");
+  }
+
+  #[test]
+  fn label_and_name_length_limits() {
+    let label = "x".repeat(MAX_LABEL_LENGTH);
+    assert!(Name::parse(&label).is_ok());
+    assert_eq!(Name::parse(&format!("{}x.com", label)), Err(ProtocolError::LabelTooLong { offset: 0, length: 64 }));
+
+    // Three full labels and one of 61 octets fill exactly 255 octets
+    let longest = format!("{0}.{0}.{0}.{1}", label, "x".repeat(61));
+    assert_eq!(name(&longest).wire_length(), MAX_NAME_LENGTH);
+    assert_eq!(Name::parse(&format!("{}x", longest)), Err(ProtocolError::NameTooLong { offset: 0 }));
+  }
+
+  #[test]
+  fn case_is_kept_but_ignored_when_comparing() {
+    let mixed = name("WWW.Example.com");
+    assert_eq!(mixed.labels()[0], b"WWW".to_vec());
+    assert_eq!(mixed, name("www.example.COM"));
+    assert_ne!(mixed, name("www.example.org"));
+    assert_ne!(name("example.com"), name("www.example.com"));
+
+    let set: HashSet<Name> = [name("Example.COM")].into_iter().collect();
+    assert!(set.contains(&name("example.com")));
+    assert!(!set.contains(&name("example.org")));
+  }
+
+  #[test]
+  fn names_sort_in_canonical_order() {
+    // The example of RFC 4034 section 6.1
+    let expected = ["example", "a.example", "yljkjljk.a.example", "Z.a.example", "zABC.a.EXAMPLE", "z.example", r"\001.z.example", "*.z.example", r"\200.z.example"];
+    let mut names: Vec<Name> = expected.iter().rev().map(|text| name(text)).collect();
+    names.sort();
+
+    let sorted: Vec<Name> = expected.iter().map(|text| name(text)).collect();
+    assert_eq!(names, sorted);
+    assert_eq!(name("A.example").cmp(&name("a.EXAMPLE")), Ordering::Equal);
+  }
+
+  #[test]
+  fn subdomains_match_on_whole_labels() {
+    assert!(name("www.example.com").is_subdomain_of(&name("example.com")));
+    assert!(name("example.com").is_subdomain_of(&name("example.com")));
+    assert!(name("WWW.EXAMPLE.com").is_subdomain_of(&name("Example.COM")));
+    assert!(name("com").is_subdomain_of(&Name::root()));
+
+    assert!(!name("example.com").is_subdomain_of(&name("ample.com")));
+    assert!(!name("example.com").is_subdomain_of(&name("www.example.com")));
+    assert!(!Name::root().is_subdomain_of(&name("com")));
+  }
+
+  #[test]
+  fn unicode_names_become_lowercase_a_labels() {
+    let bucher = Name::from_unicode("bücher.example").unwrap();
+    assert_eq!(bucher.labels()[0], b"xn--bcher-kva".to_vec());
+    assert_eq!(Name::from_unicode("BÜCHER.Example.").unwrap().labels(), bucher.labels());
+
+    // ASCII names are parsed as they are, case included
+    assert_eq!(Name::from_unicode("WWW.Example.com").unwrap().labels()[0], b"WWW".to_vec());
+  }
+
+  #[test]
+  fn invalid_unicode_names_are_rejected() {
+    for text in ["a\u{200D}b.example", "xn--ü.example"] {
+      assert_eq!(Name::from_unicode(text), Err(ProtocolError::BadIdn { text: text.to_string() }));
+    }
+  }
+
+  #[test]
+  fn a_labels_are_shown_as_unicode() {
+    assert_eq!(name("xn--bcher-kva.example").to_unicode(), "bücher.example.");
+    assert_eq!(name("XN--BCHER-KVA.example").to_unicode(), "bücher.example.");
+    assert_eq!(format!("{:#}", name("www.xn--bcher-kva.example")), "www.bücher.example.");
+    assert_eq!(format!("{}", name("www.xn--bcher-kva.example")), "www.xn--bcher-kva.example.");
+    assert_eq!(Name::root().to_unicode(), ".");
+
+    // Other labels keep their escapes
+    assert_eq!(name(r"a\.b.xn--bcher-kva").to_unicode(), r"a\.b.bücher.");
+  }
+
+  #[test]
+  fn fake_a_labels_are_left_alone() {
+    // Decodes to plain `abc`, which would never be written as an A-label
+    assert_eq!(name("xn--abc-.example").to_unicode(), "xn--abc-.example.");
+    // Not Punycode at all
+    assert_eq!(name("xn--a-ecp.example").to_unicode(), "xn--a-ecp.example.");
+    assert_eq!(name(r"xn--\255.example").to_unicode(), r"xn--\255.example.");
+  }
+
+  #[test]
+  fn unicode_round_trips() {
+    let original = name("www.xn--bcher-kva.example");
+    assert_eq!(Name::from_unicode(&original.to_unicode()).unwrap().labels(), original.labels());
+  }
+
+  #[test]
+  fn parent_drops_the_first_label() {
+    assert_eq!(name("www.example.com").parent(), Some(name("example.com")));
+    assert_eq!(name("com").parent(), Some(Name::root()));
+    assert_eq!(Name::root().parent(), None);
+  }
+
+  #[test]
+  fn from_labels_reports_where_the_bad_label_is() {
+    let labels = vec![b"www".to_vec(), vec![b'x'; 64], b"com".to_vec()];
+    assert_eq!(Name::from_labels(labels, 12).unwrap_err(), ProtocolError::LabelTooLong { offset: 16, length: 64 });
+
+    let labels = vec![b"www".to_vec(), Vec::new()];
+    assert_eq!(Name::from_labels(labels, 12).unwrap_err(), ProtocolError::EmptyLabel { offset: 16 });
+
+    let labels = vec![vec![b'x'; 63]; 4];
+    assert_eq!(Name::from_labels(labels, 12).unwrap_err(), ProtocolError::NameTooLong { offset: 12 });
+  }
+}
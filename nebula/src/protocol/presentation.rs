@@ -0,0 +1,579 @@
+use std::{error::Error, fmt, net::{Ipv4Addr, Ipv6Addr}};
+
+use super::{byte_packet::{PacketBuffer, VectorPacketBuffer}, dns_record::{is_valid_caa_tag, DnsRecord}, name::Name, query_class::QueryClass, query_type::QueryType, svcb::SvcParam};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+// Split a zone file line into fields. A quoted string stays a single field (without its quotes,
+// escapes left in place) and everything after an unquoted `;` is a comment.
+pub fn tokenize(line: &str) -> Result<Vec<String>> {
+  let mut fields = Vec::new();
+  let mut current = String::new();
+  let mut in_field = false;
+  let mut quoted = false;
+  let mut chars = line.chars();
+
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => {
+        current.push(c);
+        current.push(chars.next().ok_or("Dangling escape at end of line")?);
+        in_field = true;
+      }
+      '"' => {
+        if quoted {
+          fields.push(std::mem::take(&mut current));
+          in_field = false;
+        }
+        quoted = !quoted;
+      }
+      c if quoted => current.push(c),
+      ';' => break,
+      c if c.is_whitespace() => {
+        if in_field {
+          fields.push(std::mem::take(&mut current));
+          in_field = false;
+        }
+      }
+      c => {
+        current.push(c);
+        in_field = true;
+      }
+    }
+  }
+
+  if quoted {
+    return Err("Unterminated quoted string".into());
+  }
+  if in_field {
+    fields.push(current);
+  }
+
+  Ok(fields)
+}
+
+// Resolve the `\X` and `\DDD` escapes of a character-string into its raw bytes
+pub fn unescape(text: &str) -> Result<Vec<u8>> {
+  let mut bytes = Vec::with_capacity(text.len());
+  let mut chars = text.chars();
+
+  while let Some(c) = chars.next() {
+    if c != '\\' {
+      let mut utf8 = [0; 4];
+      bytes.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+      continue;
+    }
+
+    match chars.next() {
+      Some(d) if d.is_ascii_digit() => {
+        let mut digits = String::from(d);
+        for _ in 0..2 {
+          match chars.next() {
+            Some(d) if d.is_ascii_digit() => digits.push(d),
+            _ => return Err(format!("Bad escape \\{} in {}", digits, text).into()),
+          }
+        }
+        bytes.push(digits.parse::<u8>().map_err(|_| format!("Bad escape \\{} in {}", digits, text))?);
+      }
+      Some(c) => {
+        let mut utf8 = [0; 4];
+        bytes.extend_from_slice(c.encode_utf8(&mut utf8).as_bytes());
+      }
+      None => return Err(format!("Dangling escape in {}", text).into()),
+    }
+  }
+
+  Ok(bytes)
+}
+
+// Write a character-string with quotes, escaping anything that is not printable ASCII
+pub fn escape(bytes: &[u8]) -> String {
+  let mut text = String::with_capacity(bytes.len() + 2);
+  text.push('"');
+
+  for byte in bytes {
+    match byte {
+      b'"' | b'\\' => {
+        text.push('\\');
+        text.push(*byte as char);
+      }
+      0x20..=0x7E => text.push(*byte as char),
+      _ => text.push_str(&format!("\\{:03}", byte)),
+    }
+  }
+
+  text.push('"');
+  text
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+pub fn from_hex(text: &str) -> Result<Vec<u8>> {
+  if !text.len().is_multiple_of(2) || !text.is_ascii() {
+    return Err(format!("Bad hex string {}", text).into());
+  }
+
+  (0..text.len())
+    .step_by(2)
+    .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|_| format!("Bad hex string {}", text).into()))
+    .collect()
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Standard base64 with padding (RFC 4648 section 4)
+pub fn to_base64(bytes: &[u8]) -> String {
+  let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+  for chunk in bytes.chunks(3) {
+    let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+    let group = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | (b[2] as u32);
+
+    for i in 0..4 {
+      if i <= chunk.len() {
+        text.push(BASE64_ALPHABET[((group >> (18 - 6 * i)) & 0x3F) as usize] as char);
+      } else {
+        text.push('=');
+      }
+    }
+  }
+
+  text
+}
+
+pub fn from_base64(text: &str) -> Result<Vec<u8>> {
+  let text = text.trim_end_matches('=');
+  let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+  let mut group: u32 = 0;
+  let mut bits = 0;
+
+  for c in text.bytes() {
+    let value = BASE64_ALPHABET.iter()
+      .position(|b| *b == c)
+      .ok_or_else(|| format!("Bad base64 string {}", text))?;
+
+    group = (group << 6) | value as u32;
+    bits += 6;
+
+    if bits >= 8 {
+      bits -= 8;
+      bytes.push((group >> bits) as u8);
+      group &= (1 << bits) - 1;
+    }
+  }
+
+  Ok(bytes)
+}
+
+// Names in zone files are always fully qualified, the trailing dot is optional. Unicode names
+// are accepted and stored as A-labels.
+fn parse_name(text: &str) -> Result<Name> {
+  Name::from_unicode(text).map_err(|e| format!("Bad name {}: {}", text, e).into())
+}
+
+fn field(fields: &[String], index: usize) -> Result<&str> {
+  fields.get(index)
+    .map(|field| field.as_str())
+    .ok_or_else(|| "Missing field in record".into())
+}
+
+// Parse a single zone file line of the form `<name> <ttl> [<class>] <type> <rdata...>`, the class
+// defaulting to IN. Blank and comment only lines give `None`.
+pub fn parse_record(line: &str) -> Result<Option<DnsRecord>> {
+  let fields = tokenize(line)?;
+  if fields.is_empty() {
+    return Ok(None);
+  }
+
+  let domain = parse_name(field(&fields, 0)?)?;
+  let ttl = field(&fields, 1)?.parse::<u32>().map_err(|_| format!("Bad TTL in {}", line))?;
+
+  let mut next = 2;
+  let class = match QueryClass::from_name(field(&fields, next)?) {
+    Some(class) => {
+      next += 1;
+      class
+    }
+    None => QueryClass::IN,
+  };
+
+  let type_name = field(&fields, next)?;
+  let qtype = QueryType::from_name(type_name).ok_or_else(|| format!("Unsupported record type {}", type_name))?;
+
+  let record = parse_rdata(domain, ttl, class, qtype, &fields[next + 1..])
+    .map_err(|e| format!("{} in {}", e, line))?;
+
+  Ok(Some(record))
+}
+
+// Build a record from rdata in the generic `\# <length> <hex>` form (RFC 3597 section 5). Known
+// types are decoded from the bytes as if they had been received on the wire.
+fn parse_generic_rdata(domain: Name, ttl: u32, class: QueryClass, qtype: QueryType, rdata: &[String]) -> Result<DnsRecord> {
+  let length = field(rdata, 1)?.parse::<usize>()?;
+  let data = from_hex(&rdata[2..].concat())?;
+
+  if data.len() != length {
+    return Err(format!("Generic rdata is {} bytes long, not {}", data.len(), length).into());
+  }
+
+  if let QueryType::UNKNOWN(qtype) = qtype {
+    return Ok(DnsRecord::UNKNOWN { domain, qtype, data, class, ttl });
+  }
+
+  let mut buffer = VectorPacketBuffer::new();
+  buffer.write_qname_uncompressed(&domain)?;
+  buffer.write_u16(qtype.to_num())?;
+  buffer.write_u16(class.to_num())?;
+  buffer.write_u32(ttl)?;
+  buffer.write_u16(u16::try_from(data.len()).map_err(|_| "Generic rdata is longer than 65535 bytes")?)?;
+  for byte in &data {
+    buffer.write_u8(*byte)?;
+  }
+
+  buffer.change_buffer_position(0)?;
+  let record = DnsRecord::read(&mut buffer)?;
+
+  if buffer.current_positon_in_buffer() != buffer.buf.len() {
+    return Err(format!("Generic rdata does not match the format of {}", qtype).into());
+  }
+
+  Ok(record)
+}
+
+fn parse_rdata(domain: Name, ttl: u32, class: QueryClass, qtype: QueryType, rdata: &[String]) -> Result<DnsRecord> {
+  if rdata.first().is_some_and(|first| first == "\\#") {
+    return parse_generic_rdata(domain, ttl, class, qtype, rdata);
+  }
+
+  let expected = match qtype {
+    QueryType::A | QueryType::AAAA | QueryType::NS | QueryType::CNAME | QueryType::PTR => Some(1),
+    QueryType::MX => Some(2),
+    QueryType::SOA => Some(7),
+    QueryType::SRV => Some(4),
+    QueryType::CAA => Some(3),
+    _ => None,
+  };
+  if let Some(expected) = expected.filter(|expected| rdata.len() != *expected) {
+    return Err(format!("Expected {} rdata fields for {:?}, found {}", expected, qtype, rdata.len()).into());
+  }
+
+  let record = match qtype {
+    QueryType::A => DnsRecord::A {
+      domain,
+      addr: field(rdata, 0)?.parse::<Ipv4Addr>()?,
+      class,
+      ttl,
+    },
+    QueryType::AAAA => DnsRecord::AAAA {
+      domain,
+      addr: field(rdata, 0)?.parse::<Ipv6Addr>()?,
+      class,
+      ttl,
+    },
+    QueryType::NS => DnsRecord::NS { domain, host: parse_name(field(rdata, 0)?)?, class, ttl },
+    QueryType::CNAME => DnsRecord::CNAME { domain, host: parse_name(field(rdata, 0)?)?, class, ttl },
+    QueryType::PTR => DnsRecord::PTR { domain, host: parse_name(field(rdata, 0)?)?, class, ttl },
+    QueryType::MX => DnsRecord::MX {
+      domain,
+      priority: field(rdata, 0)?.parse()?,
+      host: parse_name(field(rdata, 1)?)?,
+      class,
+      ttl,
+    },
+    QueryType::TXT => {
+      if rdata.is_empty() {
+        return Err("TXT record without any strings".into());
+      }
+
+      DnsRecord::TXT {
+        domain,
+        data: rdata.iter().map(|text| unescape(text)).collect::<Result<Vec<_>>>()?,
+        class,
+        ttl,
+      }
+    }
+    QueryType::SOA => DnsRecord::SOA {
+      domain,
+      mname: parse_name(field(rdata, 0)?)?,
+      rname: parse_name(field(rdata, 1)?)?,
+      serial: field(rdata, 2)?.parse()?,
+      refresh: field(rdata, 3)?.parse()?,
+      retry: field(rdata, 4)?.parse()?,
+      expire: field(rdata, 5)?.parse()?,
+      minimum: field(rdata, 6)?.parse()?,
+      class,
+      ttl,
+    },
+    QueryType::SRV => DnsRecord::SRV {
+      domain,
+      priority: field(rdata, 0)?.parse()?,
+      weight: field(rdata, 1)?.parse()?,
+      port: field(rdata, 2)?.parse()?,
+      target: parse_name(field(rdata, 3)?)?,
+      class,
+      ttl,
+    },
+    QueryType::SSHFP => {
+      if rdata.len() < 3 {
+        return Err("SSHFP needs an algorithm, a fingerprint type and a fingerprint".into());
+      }
+
+      DnsRecord::SSHFP {
+        domain,
+        algorithm: field(rdata, 0)?.parse()?,
+        fingerprint_type: field(rdata, 1)?.parse()?,
+        // The hex may be split by whitespace
+        fingerprint: from_hex(&rdata[2..].concat())?,
+        class,
+        ttl,
+      }
+    }
+    QueryType::TLSA => {
+      if rdata.len() < 4 {
+        return Err("TLSA needs a usage, a selector, a matching type and data".into());
+      }
+
+      DnsRecord::TLSA {
+        domain,
+        usage: field(rdata, 0)?.parse()?,
+        selector: field(rdata, 1)?.parse()?,
+        matching_type: field(rdata, 2)?.parse()?,
+        data: from_hex(&rdata[3..].concat())?,
+        class,
+        ttl,
+      }
+    }
+    QueryType::SVCB | QueryType::HTTPS => {
+      let priority = field(rdata, 0)?.parse()?;
+      let target = parse_name(field(rdata, 1)?)?;
+      let mut params = rdata[2..].iter().map(|param| SvcParam::parse(param)).collect::<Result<Vec<_>>>()?;
+      params.sort_by_key(|param| param.key());
+
+      if params.windows(2).any(|pair| pair[0].key() == pair[1].key()) {
+        return Err("Duplicate SvcParamKey".into());
+      }
+
+      if priority == 0 && !params.is_empty() {
+        return Err("AliasMode records take no SvcParams".into());
+      }
+
+      if qtype == QueryType::SVCB {
+        DnsRecord::SVCB { domain, priority, target, params, class, ttl }
+      } else {
+        DnsRecord::HTTPS { domain, priority, target, params, class, ttl }
+      }
+    }
+    QueryType::CAA => {
+      let tag = field(rdata, 1)?;
+      if !is_valid_caa_tag(tag.as_bytes()) {
+        return Err(format!("Bad CAA tag {}", tag).into());
+      }
+
+      DnsRecord::CAA {
+        domain,
+        flags: field(rdata, 0)?.parse()?,
+        tag: tag.to_string(),
+        value: unescape(field(rdata, 2)?)?,
+        class,
+        ttl,
+      }
+    }
+    _ => return Err(format!("Records of type {:?} can not be written in a zone file", qtype).into()),
+  };
+
+  Ok(record)
+}
+
+// Presentation format, as a record would be written in a zone file. The alternate form `{:#}`
+// shows names with Unicode labels instead of A-labels.
+impl fmt::Display for DnsRecord {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    // OPT is not a real record and has no presentation format, show it the way dig does
+    if let DnsRecord::OPT { edns } = self {
+      write!(f, "; EDNS: version: {}, flags:{}; udp: {}",
+        edns.version, if edns.dnssec_ok { " do" } else { "" }, edns.udp_payload_size)?;
+      for error in edns.extended_errors() {
+        write!(f, "; EDE: {}", error)?;
+      }
+      return Ok(());
+    }
+
+    // `{:#}` shows A-labels as Unicode
+    let unicode = f.alternate();
+    let show = |name: &Name| if unicode { name.to_unicode() } else { name.to_string() };
+
+    write!(f, "{} {} {} {} ", show(self.domain()), self.ttl(), self.class(), self.query_type())?;
+
+    match self {
+      DnsRecord::A { addr, .. } => write!(f, "{}", addr),
+      DnsRecord::AAAA { addr, .. } => write!(f, "{}", addr),
+      DnsRecord::NS { host, .. }
+      | DnsRecord::CNAME { host, .. }
+      | DnsRecord::PTR { host, .. } => write!(f, "{}", show(host)),
+      DnsRecord::MX { priority, host, .. } => write!(f, "{} {}", priority, show(host)),
+      DnsRecord::TXT { data, .. } => {
+        let strings: Vec<String> = data.iter().map(|string| escape(string)).collect();
+        write!(f, "{}", strings.join(" "))
+      }
+      DnsRecord::SOA { mname, rname, serial, refresh, retry, expire, minimum, .. } => write!(f, "{} {} {} {} {} {} {}",
+        show(mname), show(rname), serial, refresh, retry, expire, minimum),
+      DnsRecord::SRV { priority, weight, port, target, .. } => write!(f, "{} {} {} {}", priority, weight, port, show(target)),
+      DnsRecord::SSHFP { algorithm, fingerprint_type, fingerprint, .. } => write!(f, "{} {} {}",
+        algorithm, fingerprint_type, to_hex(fingerprint)),
+      DnsRecord::TLSA { usage, selector, matching_type, data, .. } => write!(f, "{} {} {} {}",
+        usage, selector, matching_type, to_hex(data)),
+      DnsRecord::SVCB { priority, target, params, .. }
+      | DnsRecord::HTTPS { priority, target, params, .. } => {
+        write!(f, "{} {}", priority, show(target))?;
+        for param in params {
+          write!(f, " {}", param)?;
+        }
+        Ok(())
+      }
+      DnsRecord::CAA { flags, tag, value, .. } => write!(f, "{} {} {}", flags, tag, escape(value)),
+      DnsRecord::UNKNOWN { data, .. } => {
+        write!(f, "\\# {}", data.len())?;
+        if !data.is_empty() {
+          write!(f, " {}", to_hex(data))?;
+        }
+        Ok(())
+      }
+      DnsRecord::OPT { .. } => Ok(()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn record(line: &str) -> DnsRecord {
+    parse_record(line).unwrap().unwrap()
+  }
+
+  #[test]
+  fn tokenize_keeps_quoted_strings_and_drops_comments() {
+    let fields = tokenize(r#"txt.example. 60 TXT "two words" "semi;colon" plain\ space ; comment"#).unwrap();
+    assert_eq!(fields, vec!["txt.example.", "60", "TXT", "two words", "semi;colon", "plain\\ space"]);
+
+    assert!(tokenize(r#"a 60 TXT "open"#).is_err());
+    assert!(tokenize("   ; only a comment").unwrap().is_empty());
+  }
+
+  #[test]
+  fn unescape_resolves_both_escape_forms() {
+    assert_eq!(unescape(r"a\.b").unwrap(), b"a.b");
+    assert_eq!(unescape(r"\065\066C").unwrap(), b"ABC");
+    assert_eq!(unescape(r"\000\255").unwrap(), vec![0, 255]);
+    assert_eq!(unescape("é").unwrap(), "é".as_bytes());
+
+    assert!(unescape(r"\256").is_err());
+    assert!(unescape(r"\12x").is_err());
+    assert!(unescape("dangling\\").is_err());
+  }
+
+  #[test]
+  fn escape_round_trips_through_unescape() {
+    let bytes = b"say \"hi\" \\ \x00\x7F\xFF".to_vec();
+    let escaped = escape(&bytes);
+    assert_eq!(escaped, r#""say \"hi\" \\ \000\127\255""#);
+    assert_eq!(unescape(&escaped[1..escaped.len() - 1]).unwrap(), bytes);
+  }
+
+  #[test]
+  fn hex_round_trips() {
+    assert_eq!(from_hex("00ff7Ab3").unwrap(), vec![0x00, 0xFF, 0x7A, 0xB3]);
+    assert_eq!(to_hex(&[0x00, 0xFF, 0x7A, 0xB3]), "00FF7AB3");
+    assert!(from_hex("").unwrap().is_empty());
+
+    assert!(from_hex("abc").is_err());
+    assert!(from_hex("zz").is_err());
+    assert!(from_hex("éé").is_err());
+  }
+
+  #[test]
+  fn base64_matches_the_rfc_4648_vectors() {
+    let vectors = [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy")];
+
+    for (plain, encoded) in vectors {
+      assert_eq!(to_base64(plain.as_bytes()), encoded);
+      assert_eq!(from_base64(encoded).unwrap(), plain.as_bytes());
+    }
+
+    assert!(from_base64("Zm9v!").is_err());
+  }
+
+  #[test]
+  fn parse_record_reads_the_class_when_given() {
+    assert_eq!(record("www.example.com. 300 A 192.0.2.1"), DnsRecord::A {
+      domain: Name::parse("www.example.com").unwrap(),
+      addr: Ipv4Addr::new(192, 0, 2, 1),
+      class: QueryClass::IN,
+      ttl: 300,
+    });
+
+    assert_eq!(record("version.bind 0 CH TXT \"nebula\"").class(), QueryClass::CH);
+    assert_eq!(record("example.com 60 IN MX 10 mail.example.com"), DnsRecord::MX {
+      domain: Name::parse("example.com").unwrap(),
+      priority: 10,
+      host: Name::parse("mail.example.com").unwrap(),
+      class: QueryClass::IN,
+      ttl: 60,
+    });
+  }
+
+  #[test]
+  fn parse_record_skips_blank_and_comment_lines() {
+    assert_eq!(parse_record("").unwrap(), None);
+    assert_eq!(parse_record("; a comment").unwrap(), None);
+  }
+
+  #[test]
+  fn parse_record_rejects_malformed_lines() {
+    assert!(parse_record("www.example.com. A 192.0.2.1").is_err());
+    assert!(parse_record("www.example.com. 300 A").is_err());
+    assert!(parse_record("www.example.com. 300 A 192.0.2.1 extra").is_err());
+    assert!(parse_record("www.example.com. 300 A 192.0.2").is_err());
+    assert!(parse_record("www.example.com. 300 NOSUCHTYPE x").is_err());
+    assert!(parse_record("www..example.com. 300 A 192.0.2.1").is_err());
+    assert!(parse_record("example.com. 300 CAA 0 is-sue \"ca.example\"").is_err());
+  }
+
+  #[test]
+  fn generic_rdata_is_decoded_for_known_types() {
+    assert_eq!(record(r"a.example. 60 A \# 4 C0000201"), record("a.example. 60 A 192.0.2.1"));
+    assert_eq!(record(r"a.example. 60 TYPE65534 \# 3 ABCDEF"), DnsRecord::UNKNOWN {
+      domain: Name::parse("a.example").unwrap(),
+      qtype: 65534,
+      data: vec![0xAB, 0xCD, 0xEF],
+      class: QueryClass::IN,
+      ttl: 60,
+    });
+
+    assert!(parse_record(r"a.example. 60 A \# 5 C0000201").is_err());
+    assert!(parse_record(r"a.example. 60 A \# 3 C00002").is_err());
+  }
+
+  #[test]
+  fn displayed_records_parse_back() {
+    let lines = [
+      "www.example.com. 300 IN A 192.0.2.1",
+      "www.example.com. 300 IN AAAA 2001:db8::1",
+      "example.com. 300 IN SOA ns.example.com. hostmaster.example.com. 1 3600 600 86400 300",
+      "txt.example.com. 300 IN TXT \"one\" \"two \\\"quoted\\\"\" \"\\000\"",
+      "_sip._tcp.example.com. 300 IN SRV 10 5 5060 sip.example.com.",
+      "example.com. 300 IN CAA 0 issue \"ca.example\"",
+      "example.com. 300 IN SSHFP 1 2 ABCDEF",
+      "example.com. 300 IN HTTPS 1 . alpn=\"h2,h3\" port=443",
+      "a\\.b.example.com. 300 IN PTR host.example.com.",
+    ];
+
+    for line in lines {
+      let rec = record(line);
+      assert_eq!(rec.to_string(), line);
+      assert_eq!(record(&rec.to_string()), rec);
+    }
+  }
+}
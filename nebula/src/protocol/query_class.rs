@@ -0,0 +1,65 @@
+use std::fmt;
+
+#[derive(PartialEq, Debug, Clone, Eq, Hash, Copy, PartialOrd, Ord)]
+pub enum QueryClass {
+  UNKNOWN(u16),
+  IN,
+  CH,
+  HS,
+  NONE,
+  ANY
+}
+
+impl QueryClass {
+    pub fn to_num(self) -> u16 {
+      match self {
+          QueryClass::UNKNOWN(x) => x,
+          QueryClass::IN => 1,
+          QueryClass::CH => 3,
+          QueryClass::HS => 4,
+          QueryClass::NONE => 254,
+          QueryClass::ANY => 255,
+      }
+    }
+
+    pub fn from_num(num: u16) -> QueryClass {
+      match num {
+          1 => QueryClass::IN,
+          3 => QueryClass::CH,
+          4 => QueryClass::HS,
+          254 => QueryClass::NONE,
+          255 => QueryClass::ANY,
+          _ => QueryClass::UNKNOWN(num)
+      }
+    }
+
+    // The class for a mnemonic as written in zone files, e.g. "CH"
+    pub fn from_name(name: &str) -> Option<QueryClass> {
+      match name.to_uppercase().as_str() {
+          "IN" => Some(QueryClass::IN),
+          "CH" => Some(QueryClass::CH),
+          "HS" => Some(QueryClass::HS),
+          "NONE" => Some(QueryClass::NONE),
+          "ANY" => Some(QueryClass::ANY),
+          // The generic CLASSnnn form works for every class (RFC 3597 section 5)
+          other => other.strip_prefix("CLASS")
+            .and_then(|num| num.parse::<u16>().ok())
+            .map(QueryClass::from_num)
+      }
+    }
+
+    // Whether a record of class `self` answers a question asked in `qclass`
+    pub fn matches(self, qclass: QueryClass) -> bool {
+      qclass == QueryClass::ANY || self == qclass
+    }
+}
+
+// The mnemonic used in zone files, or the generic CLASSnnn form for classes we don't know
+impl fmt::Display for QueryClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      match self {
+          QueryClass::UNKNOWN(x) => write!(f, "CLASS{}", x),
+          other => write!(f, "{:?}", other),
+      }
+    }
+}
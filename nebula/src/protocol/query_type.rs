@@ -1,22 +1,44 @@
+use std::fmt;
+
 #[derive(PartialEq, Debug, Clone, Eq, Hash, Copy)]
 pub enum QueryType {
   UNKNOWN(u16),
   A,
   NS,
   CNAME,
+  SOA,
+  PTR,
   MX,
-  AAAA
+  TXT,
+  AAAA,
+  SRV,
+  SSHFP,
+  TLSA,
+  SVCB,
+  HTTPS,
+  OPT,
+  CAA
 }
 
 impl QueryType {
-    pub fn to_num(&self) -> u16 {
-      match *self {
+    pub fn to_num(self) -> u16 {
+      match self {
           QueryType::UNKNOWN(x) => x,
           QueryType::A => 1,
           QueryType::NS => 2,
           QueryType::CNAME => 5,
+          QueryType::SOA => 6,
+          QueryType::PTR => 12,
           QueryType::MX => 15,
+          QueryType::TXT => 16,
           QueryType::AAAA => 28,
+          QueryType::SRV => 33,
+          QueryType::SSHFP => 44,
+          QueryType::TLSA => 52,
+          QueryType::SVCB => 64,
+          QueryType::HTTPS => 65,
+          QueryType::OPT => 41,
+          QueryType::CAA => 257,
       }
     }
 
@@ -25,9 +47,54 @@ impl QueryType {
           1 =>  QueryType::A,
           2 => QueryType::NS,
           5 => QueryType::CNAME,
+          6 => QueryType::SOA,
+          12 => QueryType::PTR,
           15 => QueryType::MX,
+          16 => QueryType::TXT,
           28 => QueryType::AAAA,
+          33 => QueryType::SRV,
+          44 => QueryType::SSHFP,
+          52 => QueryType::TLSA,
+          64 => QueryType::SVCB,
+          65 => QueryType::HTTPS,
+          41 => QueryType::OPT,
+          257 => QueryType::CAA,
           _ => QueryType::UNKNOWN(num)
       }
     }
-}
\ No newline at end of file
+
+    // The type for a mnemonic as written in zone files, e.g. "AAAA"
+    pub fn from_name(name: &str) -> Option<QueryType> {
+      match name.to_uppercase().as_str() {
+          "A" => Some(QueryType::A),
+          "NS" => Some(QueryType::NS),
+          "CNAME" => Some(QueryType::CNAME),
+          "SOA" => Some(QueryType::SOA),
+          "PTR" => Some(QueryType::PTR),
+          "MX" => Some(QueryType::MX),
+          "TXT" => Some(QueryType::TXT),
+          "AAAA" => Some(QueryType::AAAA),
+          "SRV" => Some(QueryType::SRV),
+          "SSHFP" => Some(QueryType::SSHFP),
+          "TLSA" => Some(QueryType::TLSA),
+          "SVCB" => Some(QueryType::SVCB),
+          "HTTPS" => Some(QueryType::HTTPS),
+          "OPT" => Some(QueryType::OPT),
+          "CAA" => Some(QueryType::CAA),
+          // The generic TYPEnnn form works for every type (RFC 3597 section 5)
+          other => other.strip_prefix("TYPE")
+            .and_then(|num| num.parse::<u16>().ok())
+            .map(QueryType::from_num)
+      }
+    }
+}
+
+// The mnemonic used in zone files, or the generic TYPEnnn form for types we don't know (RFC 3597)
+impl fmt::Display for QueryType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+      match self {
+          QueryType::UNKNOWN(x) => write!(f, "TYPE{}", x),
+          other => write!(f, "{:?}", other),
+      }
+    }
+}
@@ -1,26 +1,82 @@
 
 
-
+// RCODEs as combined from the header and the OPT record. Only the first sixteen fit in the
+// header, the rest need the upper eight bits carried by EDNS (RFC 6891 section 6.1.3).
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ResultCode {
-  NOERROR = 0,
-  FORMERR = 1,
-  SERVFAIL = 2,
-  NXDOMIAN = 3,
-  NOTIMP = 4,
-  REFUSED = 5,
+  UNKNOWN(u16),
+  NOERROR, //0
+  FORMERR, //1
+  SERVFAIL, //2
+  NXDOMIAN, //3
+  NOTIMP, //4
+  REFUSED, //5
+  YXDOMAIN, //6
+  YXRRSET, //7
+  NXRRSET, //8
+  NOTAUTH, //9
+  NOTZONE, //10
+  DSOTYPENI, //11
+  BADVERS, //16
+  BADKEY, //17
+  BADTIME, //18
+  BADMODE, //19
+  BADNAME, //20
+  BADALG, //21
+  BADTRUNC, //22
+  BADCOOKIE, //23
 }
 
 impl ResultCode {
-    pub fn from_num(num: u8) -> ResultCode {
+    pub fn to_num(self) -> u16 {
+      match self {
+          ResultCode::UNKNOWN(x) => x,
+          ResultCode::NOERROR => 0,
+          ResultCode::FORMERR => 1,
+          ResultCode::SERVFAIL => 2,
+          ResultCode::NXDOMIAN => 3,
+          ResultCode::NOTIMP => 4,
+          ResultCode::REFUSED => 5,
+          ResultCode::YXDOMAIN => 6,
+          ResultCode::YXRRSET => 7,
+          ResultCode::NXRRSET => 8,
+          ResultCode::NOTAUTH => 9,
+          ResultCode::NOTZONE => 10,
+          ResultCode::DSOTYPENI => 11,
+          ResultCode::BADVERS => 16,
+          ResultCode::BADKEY => 17,
+          ResultCode::BADTIME => 18,
+          ResultCode::BADMODE => 19,
+          ResultCode::BADNAME => 20,
+          ResultCode::BADALG => 21,
+          ResultCode::BADTRUNC => 22,
+          ResultCode::BADCOOKIE => 23,
+      }
+    }
+
+    pub fn from_num(num: u16) -> ResultCode {
       match num {
+          0 => ResultCode::NOERROR,
           1 => ResultCode::FORMERR,
           2 => ResultCode::SERVFAIL,
           3 => ResultCode::NXDOMIAN,
           4 => ResultCode::NOTIMP,
           5 => ResultCode::REFUSED,
-          0 => ResultCode::NOERROR,
-          _ => ResultCode::NOERROR,
+          6 => ResultCode::YXDOMAIN,
+          7 => ResultCode::YXRRSET,
+          8 => ResultCode::NXRRSET,
+          9 => ResultCode::NOTAUTH,
+          10 => ResultCode::NOTZONE,
+          11 => ResultCode::DSOTYPENI,
+          16 => ResultCode::BADVERS,
+          17 => ResultCode::BADKEY,
+          18 => ResultCode::BADTIME,
+          19 => ResultCode::BADMODE,
+          20 => ResultCode::BADNAME,
+          21 => ResultCode::BADALG,
+          22 => ResultCode::BADTRUNC,
+          23 => ResultCode::BADCOOKIE,
+          _ => ResultCode::UNKNOWN(num),
       }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,134 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use super::name::Name;
+
+// The name a reverse lookup for `addr` is made under
+pub fn reverse_name(addr: IpAddr) -> Name {
+  match addr {
+    IpAddr::V4(addr) => ipv4_reverse_name(addr),
+    IpAddr::V6(addr) => ipv6_reverse_name(addr),
+  }
+}
+
+// Reverse names are at most 74 octets long, they always make a valid name
+fn reverse_labels(labels: Vec<String>) -> Name {
+  Name::from_labels(labels.into_iter().map(String::into_bytes).collect(), 0)
+    .expect("Reverse names are always valid")
+}
+
+// e.g. 192.0.2.1 -> 1.2.0.192.in-addr.arpa
+pub fn ipv4_reverse_name(addr: Ipv4Addr) -> Name {
+  let mut labels: Vec<String> = addr.octets().iter().rev().map(|octet| octet.to_string()).collect();
+  labels.extend(["in-addr".to_string(), "arpa".to_string()]);
+
+  reverse_labels(labels)
+}
+
+// One label per nibble, least significant first (RFC 3596 section 2.5)
+pub fn ipv6_reverse_name(addr: Ipv6Addr) -> Name {
+  let mut labels = Vec::with_capacity(34);
+
+  for byte in addr.octets().iter().rev() {
+    labels.push(format!("{:x}", byte & 0x0F));
+    labels.push(format!("{:x}", byte >> 4));
+  }
+  labels.extend(["ip6".to_string(), "arpa".to_string()]);
+
+  reverse_labels(labels)
+}
+
+// The address a full in-addr.arpa or ip6.arpa name stands for
+pub fn parse_reverse_name(name: &Name) -> Option<IpAddr> {
+  let labels: Vec<String> = name.labels()
+    .iter()
+    .map(|label| String::from_utf8(label.to_ascii_lowercase()))
+    .collect::<Result<_, _>>()
+    .ok()?;
+
+  match labels.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+    [parts @ .., "in-addr", "arpa"] => {
+      if parts.len() != 4 {
+        return None;
+      }
+
+      let mut octets = [0u8; 4];
+      for (i, part) in parts.iter().rev().enumerate() {
+        // Reject leading zeroes so every address has exactly one name
+        if part.len() > 1 && part.starts_with('0') {
+          return None;
+        }
+        octets[i] = part.parse().ok()?;
+      }
+
+      Some(IpAddr::V4(Ipv4Addr::from(octets)))
+    }
+    [nibbles @ .., "ip6", "arpa"] => {
+      if nibbles.len() != 32 {
+        return None;
+      }
+
+      let mut octets = [0u8; 16];
+      for (i, nibble) in nibbles.iter().rev().enumerate() {
+        if nibble.len() != 1 {
+          return None;
+        }
+        let value = u8::from_str_radix(nibble, 16).ok()?;
+        octets[i / 2] |= if i % 2 == 0 { value << 4 } else { value };
+      }
+
+      Some(IpAddr::V6(Ipv6Addr::from(octets)))
+    }
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util::name;
+
+  #[test]
+  fn ipv4_reverse_name_reverses_the_octets() {
+    assert_eq!(ipv4_reverse_name(Ipv4Addr::new(192, 0, 2, 1)), name("1.2.0.192.in-addr.arpa"));
+  }
+
+  #[test]
+  fn ipv6_reverse_name_puts_the_lowest_nibble_first() {
+    let expected = name("1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa");
+    assert_eq!(ipv6_reverse_name("2001:db8::1".parse().unwrap()), expected);
+  }
+
+  #[test]
+  fn reverse_names_round_trip() {
+    for addr in ["192.0.2.1", "0.0.0.0", "255.255.255.255", "2001:db8::1", "::", "fe80::abcd:ef01"] {
+      let addr: IpAddr = addr.parse().unwrap();
+      assert_eq!(parse_reverse_name(&reverse_name(addr)), Some(addr));
+    }
+  }
+
+  #[test]
+  fn parse_reverse_name_ignores_case() {
+    let upper = name("1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.B.D.0.1.0.0.2.IP6.ARPA");
+    assert_eq!(parse_reverse_name(&upper), Some("2001:db8::1".parse().unwrap()));
+  }
+
+  #[test]
+  fn parse_reverse_name_rejects_leading_zeroes_and_bad_octets() {
+    assert_eq!(parse_reverse_name(&name("01.2.0.192.in-addr.arpa")), None);
+    assert_eq!(parse_reverse_name(&name("256.2.0.192.in-addr.arpa")), None);
+    assert_eq!(parse_reverse_name(&name("x.2.0.192.in-addr.arpa")), None);
+    assert_eq!(parse_reverse_name(&name("0.2.0.192.in-addr.arpa")), Some("192.0.2.0".parse().unwrap()));
+  }
+
+  #[test]
+  fn parse_reverse_name_rejects_wrong_label_counts() {
+    assert_eq!(parse_reverse_name(&name("2.0.192.in-addr.arpa")), None);
+    assert_eq!(parse_reverse_name(&name("1.1.2.0.192.in-addr.arpa")), None);
+
+    let short = name("0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa");
+    assert_eq!(parse_reverse_name(&short), None);
+    let wide = name("10.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa");
+    assert_eq!(parse_reverse_name(&wide), None);
+    assert_eq!(parse_reverse_name(&name("www.example.com")), None);
+  }
+}
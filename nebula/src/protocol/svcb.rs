@@ -0,0 +1,284 @@
+use std::{fmt, net::{Ipv4Addr, Ipv6Addr}};
+
+use super::{byte_packet::PacketBuffer, error::ProtocolError, presentation::{from_base64, to_base64, unescape}};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+type WireResult<T> = std::result::Result<T, ProtocolError>;
+
+// A single SvcParam of an SVCB or HTTPS record (RFC 9460 section 7)
+#[derive(Debug, PartialEq, Eq, Clone, Hash, PartialOrd, Ord)]
+pub enum SvcParam {
+  Mandatory(Vec<u16>), //0
+  Alpn(Vec<Vec<u8>>), //1
+  NoDefaultAlpn, //2
+  Port(u16), //3
+  Ipv4Hint(Vec<Ipv4Addr>), //4
+  Ech(Vec<u8>), //5
+  Ipv6Hint(Vec<Ipv6Addr>), //6
+  Unknown {
+    key: u16,
+    value: Vec<u8>
+  }
+}
+
+// The presentation name of a SvcParamKey
+pub fn key_name(key: u16) -> String {
+  match key {
+    0 => "mandatory".to_string(),
+    1 => "alpn".to_string(),
+    2 => "no-default-alpn".to_string(),
+    3 => "port".to_string(),
+    4 => "ipv4hint".to_string(),
+    5 => "ech".to_string(),
+    6 => "ipv6hint".to_string(),
+    _ => format!("key{}", key),
+  }
+}
+
+pub fn key_from_name(name: &str) -> Result<u16> {
+  match name {
+    "mandatory" => Ok(0),
+    "alpn" => Ok(1),
+    "no-default-alpn" => Ok(2),
+    "port" => Ok(3),
+    "ipv4hint" => Ok(4),
+    "ech" => Ok(5),
+    "ipv6hint" => Ok(6),
+    _ => name.strip_prefix("key")
+      .and_then(|number| number.parse::<u16>().ok())
+      .ok_or_else(|| format!("Unknown SvcParamKey {}", name).into()),
+  }
+}
+
+// Split a comma separated value list, honouring `\,` escapes
+fn split_list(text: &str) -> Vec<String> {
+  let mut items = Vec::new();
+  let mut current = String::new();
+  let mut chars = text.chars();
+
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => {
+        current.push(c);
+        if let Some(next) = chars.next() {
+          current.push(next);
+        }
+      }
+      ',' => items.push(std::mem::take(&mut current)),
+      c => current.push(c),
+    }
+  }
+  items.push(current);
+
+  items
+}
+
+impl SvcParam {
+  pub fn key(&self) -> u16 {
+    match self {
+      SvcParam::Mandatory(_) => 0,
+      SvcParam::Alpn(_) => 1,
+      SvcParam::NoDefaultAlpn => 2,
+      SvcParam::Port(_) => 3,
+      SvcParam::Ipv4Hint(_) => 4,
+      SvcParam::Ech(_) => 5,
+      SvcParam::Ipv6Hint(_) => 6,
+      SvcParam::Unknown { key, .. } => *key,
+    }
+  }
+
+  // Read the value of a parameter whose key and length have already been read
+  pub fn read<T: PacketBuffer>(buffer: &mut T, key: u16, length: usize) -> WireResult<SvcParam> {
+    let offset = buffer.current_positon_in_buffer();
+    let value = buffer.read_bytes(length)?;
+    let bad = |reason| ProtocolError::BadRdata { offset, reason };
+
+    let param = match key {
+      0 => {
+        if value.is_empty() || !value.len().is_multiple_of(2) {
+          return Err(bad("Bad mandatory SvcParam length"));
+        }
+        SvcParam::Mandatory(value.chunks(2).map(|key| u16::from_be_bytes([key[0], key[1]])).collect())
+      }
+      1 => {
+        let mut ids = Vec::new();
+        let mut rest = &value[..];
+        while let Some((len, tail)) = rest.split_first() {
+          let len = *len as usize;
+          if len == 0 || len > tail.len() {
+            return Err(bad("Bad alpn SvcParam"));
+          }
+          ids.push(tail[..len].to_vec());
+          rest = &tail[len..];
+        }
+        if ids.is_empty() {
+          return Err(bad("Empty alpn SvcParam"));
+        }
+        SvcParam::Alpn(ids)
+      }
+      2 => {
+        if !value.is_empty() {
+          return Err(bad("no-default-alpn SvcParam must be empty"));
+        }
+        SvcParam::NoDefaultAlpn
+      }
+      3 => {
+        if value.len() != 2 {
+          return Err(bad("Bad port SvcParam length"));
+        }
+        SvcParam::Port(u16::from_be_bytes([value[0], value[1]]))
+      }
+      4 => {
+        if value.is_empty() || !value.len().is_multiple_of(4) {
+          return Err(bad("Bad ipv4hint SvcParam length"));
+        }
+        SvcParam::Ipv4Hint(value.chunks(4).map(|addr| Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3])).collect())
+      }
+      5 => SvcParam::Ech(value),
+      6 => {
+        if value.is_empty() || !value.len().is_multiple_of(16) {
+          return Err(bad("Bad ipv6hint SvcParam length"));
+        }
+        SvcParam::Ipv6Hint(value.chunks(16).map(|addr| {
+          let mut octets = [0u8; 16];
+          octets.copy_from_slice(addr);
+          Ipv6Addr::from(octets)
+        }).collect())
+      }
+      _ => SvcParam::Unknown { key, value },
+    };
+
+    Ok(param)
+  }
+
+  // The wire format value, without the key and length
+  fn value(&self) -> Vec<u8> {
+    match self {
+      SvcParam::Mandatory(keys) => keys.iter().flat_map(|key| key.to_be_bytes()).collect(),
+      SvcParam::Alpn(ids) => ids.iter().flat_map(|id| std::iter::once(id.len() as u8).chain(id.iter().copied())).collect(),
+      SvcParam::NoDefaultAlpn => Vec::new(),
+      SvcParam::Port(port) => port.to_be_bytes().to_vec(),
+      SvcParam::Ipv4Hint(addrs) => addrs.iter().flat_map(|addr| addr.octets()).collect(),
+      SvcParam::Ech(config) => config.clone(),
+      SvcParam::Ipv6Hint(addrs) => addrs.iter().flat_map(|addr| addr.octets()).collect(),
+      SvcParam::Unknown { value, .. } => value.clone(),
+    }
+  }
+
+  pub fn write<T: PacketBuffer>(&self, buffer: &mut T) -> WireResult<()> {
+    let value = self.value();
+
+    buffer.write_u16(self.key())?;
+
+    let pos = buffer.current_positon_in_buffer();
+    buffer.write_u16(0)?;
+    for byte in value {
+      buffer.write_u8(byte)?;
+    }
+    buffer.set_length(pos)?;
+
+    Ok(())
+  }
+
+  // Parse a `key=value` (or bare `key`) field of a zone file
+  pub fn parse(text: &str) -> Result<SvcParam> {
+    let (name, value) = match text.split_once('=') {
+      Some((name, value)) => (name, Some(value)),
+      None => (text, None),
+    };
+    let key = key_from_name(name)?;
+
+    let Some(value) = value else {
+      return match key {
+        2 => Ok(SvcParam::NoDefaultAlpn),
+        0..=6 => Err(format!("SvcParam {} needs a value", name).into()),
+        _ => Ok(SvcParam::Unknown { key, value: Vec::new() }),
+      };
+    };
+
+    let param = match key {
+      0 => SvcParam::Mandatory(value.split(',').map(key_from_name).collect::<Result<Vec<_>>>()?),
+      1 => SvcParam::Alpn(split_list(value).iter().map(|id| unescape(id)).collect::<Result<Vec<_>>>()?),
+      2 => return Err("no-default-alpn takes no value".into()),
+      3 => SvcParam::Port(value.parse()?),
+      4 => SvcParam::Ipv4Hint(value.split(',').map(|addr| addr.parse::<Ipv4Addr>()).collect::<std::result::Result<Vec<_>, _>>()?),
+      5 => SvcParam::Ech(from_base64(value)?),
+      6 => SvcParam::Ipv6Hint(value.split(',').map(|addr| addr.parse::<Ipv6Addr>()).collect::<std::result::Result<Vec<_>, _>>()?),
+      _ => SvcParam::Unknown { key, value: unescape(value)? },
+    };
+
+    Ok(param)
+  }
+}
+
+// Read the SvcParams filling the rest of the rdata. Keys have to be strictly increasing.
+pub fn read_params<T: PacketBuffer>(buffer: &mut T, length: usize) -> WireResult<Vec<SvcParam>> {
+  let end = buffer.current_positon_in_buffer() + length;
+  let mut params: Vec<SvcParam> = Vec::new();
+
+  while buffer.current_positon_in_buffer() < end {
+    let key = buffer.read_two_bytes()?;
+    let value_length = buffer.read_two_bytes()? as usize;
+
+    if params.last().is_some_and(|last| last.key() >= key) {
+      return Err(ProtocolError::BadRdata { offset: buffer.current_positon_in_buffer() - 4, reason: "SvcParam keys out of order" });
+    }
+
+    params.push(SvcParam::read(buffer, key, value_length)?);
+  }
+
+  if buffer.current_positon_in_buffer() != end {
+    return Err(ProtocolError::BadRdata { offset: end, reason: "SvcParams overrun the record data" });
+  }
+
+  Ok(params)
+}
+
+// Write SvcParams in the ascending key order the wire format requires
+pub fn write_params<T: PacketBuffer>(buffer: &mut T, params: &[SvcParam]) -> WireResult<()> {
+  let mut sorted: Vec<&SvcParam> = params.iter().collect();
+  sorted.sort_by_key(|param| param.key());
+
+  for param in sorted {
+    param.write(buffer)?;
+  }
+
+  Ok(())
+}
+
+impl fmt::Display for SvcParam {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = key_name(self.key());
+
+    match self {
+      SvcParam::Mandatory(keys) => {
+        let names: Vec<String> = keys.iter().map(|key| key_name(*key)).collect();
+        write!(f, "{}={}", name, names.join(","))
+      }
+      SvcParam::Alpn(ids) => {
+        let ids: Vec<String> = ids.iter()
+          .map(|id| String::from_utf8_lossy(id).replace('\\', "\\\\").replace(',', "\\,"))
+          .collect();
+        write!(f, "{}=\"{}\"", name, ids.join(","))
+      }
+      SvcParam::NoDefaultAlpn => write!(f, "{}", name),
+      SvcParam::Port(port) => write!(f, "{}={}", name, port),
+      SvcParam::Ipv4Hint(addrs) => {
+        let addrs: Vec<String> = addrs.iter().map(|addr| addr.to_string()).collect();
+        write!(f, "{}={}", name, addrs.join(","))
+      }
+      SvcParam::Ech(config) => write!(f, "{}={}", name, to_base64(config)),
+      SvcParam::Ipv6Hint(addrs) => {
+        let addrs: Vec<String> = addrs.iter().map(|addr| addr.to_string()).collect();
+        write!(f, "{}={}", name, addrs.join(","))
+      }
+      SvcParam::Unknown { value, .. } => {
+        let value: String = value.iter().map(|byte| match byte {
+          0x21..=0x7E if *byte != b'"' && *byte != b'\\' => (*byte as char).to_string(),
+          _ => format!("\\{:03}", byte),
+        }).collect();
+        write!(f, "{}=\"{}\"", name, value)
+      }
+    }
+  }
+}
@@ -0,0 +1,26 @@
+// Fixtures shared by the unit tests of every module
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::protocol::{dns_record::DnsRecord, name::Name, query_class::QueryClass};
+
+pub fn name(text: &str) -> Name {
+  Name::parse(text).unwrap()
+}
+
+// An A record in the documentation range 192.0.2.0/24
+pub fn a_record(domain: &str, last_octet: u8) -> DnsRecord {
+  DnsRecord::A { domain: name(domain), addr: Ipv4Addr::new(192, 0, 2, last_octet), class: QueryClass::IN, ttl: 300 }
+}
+
+// An AAAA record in the documentation range 2001:db8::/32
+pub fn aaaa_record(domain: &str, last_segment: u16) -> DnsRecord {
+  DnsRecord::AAAA { domain: name(domain), addr: Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, last_segment), class: QueryClass::IN, ttl: 300 }
+}
+
+pub fn ns_record(domain: &str, host: &str) -> DnsRecord {
+  DnsRecord::NS { domain: name(domain), host: name(host), class: QueryClass::IN, ttl: 300 }
+}
+
+pub fn cname_record(domain: &str, host: &str) -> DnsRecord {
+  DnsRecord::CNAME { domain: name(domain), host: name(host), class: QueryClass::IN, ttl: 300 }
+}
@@ -0,0 +1,88 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+use crate::protocol::byte_packet::BytePacketBuffer;
+
+// Read one length prefixed DNS message from a TCP stream (RFC 1035 section 4.2.2). Returns
+// `None` when the peer closed the connection cleanly between two messages, a close anywhere
+// inside a message, its length included, is an error.
+pub fn read_tcp_message<R: Read>(stream: &mut R) -> io::Result<Option<BytePacketBuffer>> {
+  let mut len_buf = [0; 2];
+
+  loop {
+    match stream.read(&mut len_buf[..1]) {
+      Ok(0) => return Ok(None),
+      Ok(_) => break,
+      Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+      Err(e) => return Err(e),
+    }
+  }
+  stream.read_exact(&mut len_buf[1..])?;
+
+  let length = u16::from_be_bytes(len_buf) as usize;
+  let mut buffer = BytePacketBuffer::with_size(length);
+  stream.read_exact(&mut buffer.buf)?;
+
+  Ok(Some(buffer))
+}
+
+// Write one DNS message to a TCP stream, prefixed with its two byte length
+pub fn write_tcp_message<W: Write>(stream: &mut W, data: &[u8]) -> io::Result<()> {
+  let length = u16::try_from(data.len())
+    .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "Message too large for TCP"))?;
+
+  let mut message = Vec::with_capacity(data.len() + 2);
+  message.extend_from_slice(&length.to_be_bytes());
+  message.extend_from_slice(data);
+
+  stream.write_all(&message)?;
+  stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::Cursor;
+
+  use super::*;
+
+  #[test]
+  fn message_round_trips() {
+    let mut stream = Vec::new();
+    write_tcp_message(&mut stream, b"first").unwrap();
+    write_tcp_message(&mut stream, b"").unwrap();
+    assert_eq!(&stream[..2], &[0, 5]);
+
+    let mut stream = Cursor::new(stream);
+    assert_eq!(read_tcp_message(&mut stream).unwrap().unwrap().buf, b"first");
+    assert!(read_tcp_message(&mut stream).unwrap().unwrap().buf.is_empty());
+    assert!(read_tcp_message(&mut stream).unwrap().is_none());
+  }
+
+  #[test]
+  fn close_between_messages_is_clean() {
+    assert!(read_tcp_message(&mut Cursor::new(Vec::new())).unwrap().is_none());
+  }
+
+  #[test]
+  fn close_inside_the_length_is_an_error() {
+    let result = read_tcp_message(&mut Cursor::new(vec![0]));
+    assert!(result.is_err_and(|e| e.kind() == ErrorKind::UnexpectedEof));
+  }
+
+  #[test]
+  fn close_inside_the_body_is_an_error() {
+    let result = read_tcp_message(&mut Cursor::new(vec![0, 5, 1, 2]));
+    assert!(result.is_err_and(|e| e.kind() == ErrorKind::UnexpectedEof));
+  }
+
+  #[test]
+  fn message_over_65535_bytes_is_not_written() {
+    let mut stream = Vec::new();
+    let error = write_tcp_message(&mut stream, &vec![0; 65536]).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    assert!(stream.is_empty());
+
+    write_tcp_message(&mut stream, &vec![0; 65535]).unwrap();
+    assert_eq!(&stream[..2], &[0xFF, 0xFF]);
+    assert_eq!(stream.len(), 65537);
+  }
+}